@@ -32,6 +32,14 @@ fn bench_encode_pride_and_prejudice(b: &mut Criterion) {
             }
         })
     });
+    g.bench_function("count", |b| {
+        let tokenizer = Kitoken::from_tiktoken_file(bench_models_path().join(MODEL_PATH)).unwrap();
+        b.iter(|| {
+            for _ in 0..10 {
+                black_box(tokenizer.count_tokens(black_box(&text), true).unwrap());
+            }
+        })
+    });
     let lines = read_data_lines("pride_and_prejudice.txt");
     g.bench_function("lines", |b| {
         let tokenizer = Kitoken::from_tiktoken_file(bench_models_path().join(MODEL_PATH)).unwrap();
@@ -43,6 +51,20 @@ fn bench_encode_pride_and_prejudice(b: &mut Criterion) {
             }
         })
     });
+    g.bench_function("lines session", |b| {
+        let tokenizer = Kitoken::from_tiktoken_file(bench_models_path().join(MODEL_PATH)).unwrap();
+        let mut session = tokenizer.encoder_state();
+        let mut tokens = Vec::new();
+        b.iter(|| {
+            for _ in 0..10 {
+                for line in &lines {
+                    tokens.clear();
+                    session.encode_into(black_box(line), true, &mut tokens).unwrap();
+                    black_box(&tokens);
+                }
+            }
+        })
+    });
     g.finish();
 }
 
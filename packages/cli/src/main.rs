@@ -144,20 +144,26 @@ pub fn main() {
                 eprintln!("{}", error);
                 std::process::exit(1);
             });
-            let mut buffer = String::with_capacity(1024);
             if inputp.is_file() {
-                let mut reader = BufReader::new(File::open(inputp).unwrap());
-                reader.read_to_string(&mut buffer).unwrap();
+                let reader = File::open(inputp).unwrap();
+                for result in encoder.encode_reader(reader, true) {
+                    let result = result.unwrap_or_else(|error| {
+                        eprintln!("{}", error);
+                        std::process::exit(1);
+                    });
+                    for token in result {
+                        print!("{} ", token);
+                    }
+                }
             } else {
                 println!("No such file \"{}\", assuming literal input", input);
-                buffer.push_str(&input);
-            }
-            let result = encoder.encode(&buffer, true).unwrap_or_else(|error| {
-                eprintln!("{}", error);
-                std::process::exit(1);
-            });
-            for token in result {
-                print!("{} ", token);
+                let result = encoder.encode(&input, true).unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                });
+                for token in result {
+                    print!("{} ", token);
+                }
             }
             println!()
         }
@@ -222,9 +228,12 @@ pub fn convert(
         kitoken::Model::BytePair { .. } => eprintln!("Model type: BPE"),
         kitoken::Model::Unigram { .. } => eprintln!("Model type: Unigram"),
         kitoken::Model::WordPiece { .. } => eprintln!("Model type: WordPiece"),
+        kitoken::Model::WordLevel { .. } => eprintln!("Model type: WordLevel"),
+        kitoken::Model::Bytes { .. } => eprintln!("Model type: Bytes"),
+        kitoken::Model::Chars => eprintln!("Model type: Chars"),
         _ => {}
     }
-    eprintln!("Vocab size: {}", definition.model.vocab().len());
+    eprintln!("Vocab size: {}", definition.model.vocab_size());
     eprintln!("Specials size: {}", definition.specials.len());
     if let Some(out) = out {
         let mut writer = BufWriter::new(File::create(&out)?);
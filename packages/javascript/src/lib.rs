@@ -44,12 +44,18 @@ impl Kitoken {
     /// Accepted are arrays of strings "control", "priority", "unknown", and boolean values `true` and `false`.
     /// When `true`, all special token categories from the special vocabulary are used.
     ///
+    /// `add_special_tokens` specifies whether the special tokens of the sequence templates, like BOS and EOS, are inserted around the tokens.
+    ///
     /// Returns a list of tokens, or an error if no token for a part exists in the encoder and no unknown token id is set in the configuration.
-    pub fn encode(&self, text: &str, encode_specials: JsValue) -> Result<Vec<u32>, JsValue> {
+    pub fn encode(
+        &self, text: &str, encode_specials: JsValue, add_special_tokens: Option<bool>,
+    ) -> Result<Vec<u32>, JsValue> {
         let specials = convert_special_kinds(encode_specials)?;
-        match specials {
-            Either::Left(b) => self.inner.encode(text, b),
-            Either::Right(v) => self.inner.encode(text, v),
+        match (specials, add_special_tokens.unwrap_or(false)) {
+            (Either::Left(b), false) => self.inner.encode(text, b),
+            (Either::Right(v), false) => self.inner.encode(text, v),
+            (Either::Left(b), true) => self.inner.encode_with_templates(text, b),
+            (Either::Right(v), true) => self.inner.encode_with_templates(text, v),
         }
         .map_err(convert_error)
     }
@@ -66,17 +72,10 @@ impl Kitoken {
     ) -> Result<Vec<JsValue>, JsValue> {
         let specials = convert_special_kinds(encode_specials)?;
         match specials {
-            Either::Left(b) => text
-                .iter()
-                .map(|text| self.inner.encode(text, b))
-                .map(|result| result.map(JsValue::from))
-                .collect::<Result<_, _>>(),
-            Either::Right(v) => text
-                .iter()
-                .map(|text| self.inner.encode(text, v.as_slice()))
-                .map(|result| result.map(JsValue::from))
-                .collect::<Result<_, _>>(),
+            Either::Left(b) => self.inner.encode_batch(&text, b),
+            Either::Right(v) => self.inner.encode_batch(&text, v.as_slice()),
         }
+        .map(|tokens| tokens.into_iter().map(JsValue::from).collect())
         .map_err(convert_error)
     }
 
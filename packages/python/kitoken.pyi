@@ -38,7 +38,10 @@ class Kitoken:
         ...
 
     def encode(
-        self, text: str, encode_specials: Optional[bool | list[str]] = False
+        self,
+        text: str,
+        encode_specials: Optional[bool | list[str]] = False,
+        add_special_tokens: bool = False,
     ) -> list[int]:
         """
         Encodes the given text into a sequence of tokens.
@@ -47,6 +50,7 @@ class Kitoken:
         :param text: The text to encode.
         :param encode_specials: Specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
         Accepted are lists of "control", "priority", "unknown", and boolean values `True` and `False`. When `True`, all special token categories from the special vocabulary are used.
+        :param add_special_tokens: Specifies whether the special tokens of the sequence templates, like BOS and EOS, are inserted around the tokens.
         """
         ...
 
@@ -30,16 +30,18 @@ impl Kitoken {
         })
     }
 
-    #[pyo3(signature = (text, encode_specials=Either::Left(false)))]
+    #[pyo3(signature = (text, encode_specials=Either::Left(false), add_special_tokens=false))]
     pub fn encode<'a>(
         &self, text: Bound<'a, PyString>, encode_specials: Option<Either<bool, Vec<String>>>,
-        py: Python<'a>,
+        add_special_tokens: bool, py: Python<'a>,
     ) -> PyResult<Bound<'a, PyList>> {
         let text = text.extract::<&str>()?;
         let specials = convert_special_kinds(encode_specials).map_err(convert_error)?;
-        py.detach(|| match specials {
-            Either::Left(b) => self.inner.encode(text, b),
-            Either::Right(v) => self.inner.encode(text, v),
+        py.detach(|| match (specials, add_special_tokens) {
+            (Either::Left(b), false) => self.inner.encode(text, b),
+            (Either::Right(v), false) => self.inner.encode(text, v),
+            (Either::Left(b), true) => self.inner.encode_with_templates(text, b),
+            (Either::Right(v), true) => self.inner.encode_with_templates(text, v),
         })
         .map_err(convert_error)
         .map(|tokens| PyList::new(py, tokens))
@@ -54,14 +56,8 @@ impl Kitoken {
         let text = text.extract::<Vec<String>>()?;
         let specials = convert_special_kinds(encode_specials).map_err(convert_error)?;
         py.detach(|| match specials {
-            Either::Left(b) => text
-                .iter()
-                .map(|text| self.inner.encode(text, b))
-                .collect::<Result<Vec<_>, _>>(),
-            Either::Right(v) => text
-                .iter()
-                .map(|text| self.inner.encode(text, v.as_slice()))
-                .collect::<Result<Vec<_>, _>>(),
+            Either::Left(b) => self.inner.encode_batch(&text, b),
+            Either::Right(v) => self.inner.encode_batch(&text, v.as_slice()),
         })
         .map_err(convert_error)
         .map(|tokens| PyList::new(py, tokens))
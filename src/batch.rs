@@ -0,0 +1,158 @@
+//! Batch encoding.
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::{
+    EncodeError, Kitoken, ProcessingDirection, SpecialTokenKind, SpecialTokenKinds, TokenId,
+};
+
+/// Batch of token sequences padded to the same length.
+///
+/// The sequences are stored row by row in flat lists of shape `(rows, columns)`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PaddedBatch {
+    /// The tokens of all sequences.
+    pub tokens:              Vec<TokenId>,
+    /// The attention mask, `1` for tokens of the sequences and `0` for padding tokens.
+    pub attention_mask:      Vec<u32>,
+    /// The special tokens mask, `1` for the special tokens of the sequence templates and padding tokens and `0` for all other tokens.
+    /// Special tokens encoded from the text are marked with `0`.
+    pub special_tokens_mask: Vec<u32>,
+    /// The number of sequences and the padded length of each sequence.
+    pub shape:               (usize, usize),
+}
+impl PaddedBatch {
+    /// Returns the tokens of the sequence at `index`, or `None` if the index is out of bounds.
+    #[inline(always)]
+    pub fn row(&self, index: usize) -> Option<&[TokenId]> {
+        let (rows, columns) = self.shape;
+        if index >= rows {
+            return None;
+        }
+        Some(&self.tokens[index * columns..(index + 1) * columns])
+    }
+
+    /// Appends `amount` padding tokens to the current row.
+    #[inline(always)]
+    fn push_padding(&mut self, id: TokenId, amount: usize) {
+        self.tokens.extend(core::iter::repeat_n(id, amount));
+        self.attention_mask.extend(core::iter::repeat_n(0, amount));
+        self.special_tokens_mask.extend(core::iter::repeat_n(1, amount));
+    }
+}
+
+impl Kitoken {
+    /// Encodes the given texts into sequences of tokens.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// With the `parallel` feature enabled, the texts are encoded in parallel on the global `rayon` thread pool.
+    /// The order of the output always matches the order of the input.
+    ///
+    /// Returns a list of lists of tokens, or the error of the first failing text if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_batch(
+        &self, texts: &[impl AsRef<str> + Sync], encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Vec<Vec<TokenId>>, EncodeError> {
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        map_texts(texts, |text| self.inner_encode(text, encode_specials, false))
+    }
+
+    /// Encodes the given texts into a batch of token sequences padded to the same length, for use as model input.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// The special tokens of the sequence templates are inserted like in [`encode_with_templates`](Self::encode_with_templates),
+    /// and each sequence is processed without the padding steps of the configuration.
+    /// The sequences are then padded to the length of the longest sequence, or to the length of the first [`Processing::Pad`](crate::Processing::Pad) step if it is longer,
+    /// and rounded up to a multiple of its stride. The padding token and direction are taken from the same step.
+    /// Without a padding step, the sequences are padded on the right with the special token with the identifier `"pad"`.
+    ///
+    /// With the `parallel` feature enabled, the texts are encoded in parallel on the global `rayon` thread pool.
+    ///
+    /// Returns the padded batch, or an error if the configuration has no padding step and no padding token exists,
+    /// or the error of the first failing text if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_batch_padded(
+        &self, texts: &[impl AsRef<str> + Sync], encode_specials: impl SpecialTokenKinds,
+    ) -> Result<PaddedBatch, EncodeError> {
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let (id, length, stride, direction) = match self.config.padding() {
+            Some(padding) => padding,
+            None => match self.special_by_ident("pad") {
+                Some(pad) => (pad.id, 0, 0, ProcessingDirection::Right),
+                None => return Err(EncodeError::MissingPadToken),
+            },
+        };
+        let sequences = map_texts(texts, |text| self.inner_encode_unpadded(text, encode_specials))?;
+
+        let mut columns = sequences.iter().map(|tokens| tokens.len()).max().unwrap_or(0);
+        columns = columns.max(length);
+        if stride > 0 && !columns.is_multiple_of(stride) {
+            columns += stride - columns % stride;
+        }
+
+        let rows = sequences.len();
+        let mut batch = PaddedBatch {
+            tokens:              Vec::with_capacity(rows * columns),
+            attention_mask:      Vec::with_capacity(rows * columns),
+            special_tokens_mask: Vec::with_capacity(rows * columns),
+            shape:               (rows, columns),
+        };
+        for tokens in sequences {
+            let amount = columns - tokens.len();
+            if direction == ProcessingDirection::Left {
+                batch.push_padding(id, amount);
+            }
+            for (token, inserted) in tokens {
+                batch.tokens.push(token);
+                batch.attention_mask.push(1);
+                batch.special_tokens_mask.push(inserted as u32);
+            }
+            if direction == ProcessingDirection::Right {
+                batch.push_padding(id, amount);
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Encodes the given text and inserts the special tokens of the sequence templates, without padding.
+    ///
+    /// Returns the tokens with a flag set for the inserted tokens.
+    #[inline(never)]
+    fn inner_encode_unpadded(
+        &self, text: &str, encode_specials: &[SpecialTokenKind],
+    ) -> Result<Vec<(TokenId, bool)>, EncodeError> {
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let tokens = self.encoder.encode(text, &mut parts)?;
+        let mut result = tokens.into_iter().map(|token| (token, false)).collect();
+        self.config
+            .process_tokens_unpadded(&mut result, self.templates.single_len(), |tokens| {
+                self.templates.insert_single(tokens)
+            });
+        Ok(result)
+    }
+}
+
+/// Encodes the given texts with `encode`, in parallel with the `parallel` feature enabled.
+///
+/// Returns the outputs in the order of the input, or the error of the first failing text in the order of the input.
+#[inline(always)]
+fn map_texts<T: Send>(
+    texts: &[impl AsRef<str> + Sync], encode: impl Fn(&str) -> Result<T, EncodeError> + Sync,
+) -> Result<Vec<T>, EncodeError> {
+    #[cfg(feature = "parallel")]
+    {
+        // rayon returns the error of any failing text, collect all results to find the first one
+        let results = texts.par_iter().map(|text| encode(text.as_ref())).collect::<Vec<_>>();
+        results.into_iter().collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        texts.iter().map(|text| encode(text.as_ref())).collect()
+    }
+}
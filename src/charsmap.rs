@@ -106,29 +106,47 @@ impl CharsMap {
     /// Normalizes the input string according to the character mapping.
     #[inline(never)]
     pub fn normalize(&self, original: &str) -> String {
-        use bstr::ByteSlice;
         let mut result = String::with_capacity(original.len());
-        original.as_bytes().graphemes().for_each(|grapheme| {
+        let mut position = 0;
+        self.transform_each(original, |start, end, transformed| {
+            result.push_str(&original[position..start]);
+            result.push_str(transformed);
+            position = end;
+        });
+        result.push_str(&original[position..]);
+        result
+    }
+
+    /// Returns the byte range and the replacement of each part of the input string changed by the character mapping.
+    #[inline(never)]
+    pub(crate) fn edits(&self, original: &str) -> Vec<(usize, usize, String)> {
+        let mut edits = Vec::new();
+        self.transform_each(original, |start, end, transformed| {
+            if transformed != &original[start..end] {
+                edits.push((start, end, transformed.to_string()));
+            }
+        });
+        edits
+    }
+
+    /// Calls `f` with the byte range and the transformed text of each grapheme or character of the input string with a mapping.
+    #[inline(always)]
+    fn transform_each(&self, original: &str, mut f: impl FnMut(usize, usize, &str)) {
+        use bstr::ByteSlice;
+        original.as_bytes().grapheme_indices().for_each(|(start, end, grapheme)| {
             if grapheme.len() < 6
                 && let Some(transformed) = self.transform(grapheme)
             {
-                for c in transformed.chars() {
-                    result.push(c);
-                }
+                f(start, end, &transformed.to_str_lossy());
                 return;
             }
             for (i, c) in grapheme.char_indices() {
                 let part = &grapheme[i..i + c.len_utf8()];
                 if let Some(transformed) = self.transform(part) {
-                    for c in transformed.chars() {
-                        result.push(c);
-                    }
-                } else {
-                    result.push(c);
+                    f(start + i, start + i + c.len_utf8(), &transformed.to_str_lossy());
                 }
             }
         });
-        result
     }
 }
 impl Debug for CharsMap {
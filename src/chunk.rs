@@ -0,0 +1,92 @@
+//! Token-aware text chunking.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::{EncodeError, Kitoken, SpecialTokenKinds, TokenId};
+
+/// Iterator over chunks of a text with a maximum number of tokens.
+///
+/// Created with [`Kitoken::chunk`]. Yields the byte range of each chunk in the input text and the tokens of the chunk.
+#[derive(Debug, Clone)]
+pub struct Chunks {
+    tokens:     Vec<TokenId>,
+    offsets:    Vec<(usize, usize)>,
+    boundaries: Vec<bool>,
+
+    max_tokens: usize,
+    overlap:    usize,
+    position:   usize,
+}
+impl Chunks {
+    /// Returns `true` if a chunk can start at the token at `index`.
+    #[inline(always)]
+    fn is_boundary(&self, index: usize) -> bool {
+        index == self.tokens.len() || self.boundaries[index]
+    }
+}
+impl Iterator for Chunks {
+    type Item = (Range<usize>, Vec<TokenId>);
+
+    #[inline(never)]
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        if start >= self.tokens.len() || self.max_tokens == 0 {
+            return None;
+        }
+        let mut end = (start + self.max_tokens).min(self.tokens.len());
+        if end < self.tokens.len() {
+            let min = start + self.overlap + 1;
+            if let Some(boundary) = (min..=end).rev().find(|&i| self.is_boundary(i)) {
+                end = boundary;
+            }
+        }
+        self.position = if end == self.tokens.len() {
+            end
+        } else {
+            let overlap = end.saturating_sub(self.overlap).max(start + 1);
+            let boundary = (overlap..=end).find(|&i| self.is_boundary(i));
+            boundary.unwrap_or(overlap)
+        };
+        let offsets = &self.offsets[start..end];
+        let range_start = offsets.iter().map(|&(s, _)| s).min().unwrap_or(0);
+        let range_end = offsets.iter().map(|&(_, e)| e).max().unwrap_or(0);
+        let range = range_start..range_end;
+        Some((range, self.tokens[start..end].to_vec()))
+    }
+}
+
+impl Kitoken {
+    /// Splits the given text into chunks of at most `max_tokens` tokens, with `overlap` tokens shared between consecutive chunks.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// The text is encoded once without post-tokenization processing, and chunks are cut at the start of a part of the pre-tokenization split
+    /// where possible, so that chunks don't split words. If a single part spans more than `max_tokens` tokens, the chunk is cut inside of it.
+    /// The overlap is shortened to start at a part boundary where possible, or omitted if the chunk ends at a part boundary without one in the overlap.
+    /// The overlap is limited to `max_tokens - 1` tokens.
+    ///
+    /// Each chunk is yielded with the byte range covered by its tokens in the input text, see [`encode_with_offsets`](Self::encode_with_offsets) for the offset semantics.
+    ///
+    /// Returns an iterator over the chunks, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn chunk(
+        &self, text: impl AsRef<str>, max_tokens: usize, overlap: usize,
+        encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Chunks, EncodeError> {
+        let mut boundaries = Vec::new();
+        let encoding = self.inner_encode_offsets(
+            text.as_ref(),
+            encode_specials.as_kinds(&self.meta),
+            Some(&mut boundaries),
+        )?;
+        Ok(Chunks {
+            tokens: encoding.tokens,
+            offsets: encoding.offsets,
+            boundaries,
+            max_tokens,
+            overlap: overlap.min(max_tokens.saturating_sub(1)),
+            position: 0,
+        })
+    }
+}
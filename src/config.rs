@@ -57,7 +57,9 @@ pub enum InsertionPosition {
 /// Specifies additional data to insert into the tokenization input.
 /// The `content` field contains the data to insert, and the `position` field specifies where to insert it.
 ///
-/// Only [`InsertionPosition::WordEnd`] and [`InsertionPosition::WordContinuation`] are used during tokenization.
+/// [`InsertionPosition::WordEnd`] and [`InsertionPosition::WordContinuation`] are used during tokenization.
+/// [`InsertionPosition::SequenceStart`], [`InsertionPosition::SubSequenceStart`], [`InsertionPosition::SubSequenceEnd`], [`InsertionPosition::SequenceContinuation`] and [`InsertionPosition::SequenceEnd`]
+/// are used by [`Kitoken::encode_with_templates`](crate::Kitoken::encode_with_templates) and [`Kitoken::encode_pair`](crate::Kitoken::encode_pair), with `content` referring to a special token.
 /// The other positions exist for manual lookup and future use.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serialization", derive(Deserialize, Serialize))]
@@ -66,6 +68,39 @@ pub struct Template {
     pub position: InsertionPosition,
 }
 
+/// Type ids of the tokens of a pair of sequences.
+///
+/// Used by [`Kitoken::encode_pair`](crate::Kitoken::encode_pair). Each sequence shares its type id with the
+/// [`InsertionPosition::SubSequenceStart`] and [`InsertionPosition::SubSequenceEnd`] tokens around it.
+/// Tokens of single sequences always have type id 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialization", derive(Deserialize, Serialize))]
+pub struct TemplateTypeIds {
+    /// The type id of the [`InsertionPosition::SequenceStart`] tokens.
+    pub sequence_start:        u32,
+    /// The type id of the first sequence.
+    pub first:                 u32,
+    /// The type id of the [`InsertionPosition::SequenceContinuation`] tokens.
+    pub sequence_continuation: u32,
+    /// The type id of the second sequence.
+    pub second:                u32,
+    /// The type id of the [`InsertionPosition::SequenceEnd`] tokens.
+    pub sequence_end:          u32,
+}
+/// Type id 1 for the second sequence and the tokens following it, type id 0 for all other tokens.
+impl Default for TemplateTypeIds {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            sequence_start:        0,
+            first:                 0,
+            sequence_continuation: 0,
+            second:                1,
+            sequence_end:          1,
+        }
+    }
+}
+
 /// Errors returned when the configuration fails to validate.
 #[non_exhaustive]
 #[derive(Debug, thiserror::Error)]
@@ -93,6 +128,8 @@ pub struct Configuration {
     pub decoding:      Vec<Decoding>,
     /// The input templates.
     pub templates:     Vec<Template>,
+    /// The type ids of the input templates for pairs of sequences.
+    pub type_ids:      TemplateTypeIds,
 }
 
 impl Configuration {
@@ -138,6 +175,19 @@ impl Configuration {
         }
     }
 
+    /// Normalizes the input like [`normalize`](Self::normalize) and maps the alignment of the input to the output.
+    #[inline(never)]
+    pub(crate) fn normalize_aligned(
+        &self, text: &mut Cow<str>, position: Range<usize>, alignment: &mut Alignment,
+    ) {
+        if text.is_empty() {
+            return;
+        }
+        for norm in &self.normalization {
+            norm.normalize_aligned(text, position.clone(), Some(alignment));
+        }
+    }
+
     /// Splits the input into parts to tokenize.
     #[inline(never)]
     pub fn split(&self, text: &str) -> Vec<(usize, usize)> {
@@ -166,14 +216,94 @@ impl Configuration {
     }
 
     /// Processes the tokens after tokenization.
-    #[inline(never)]
+    #[inline(always)]
     pub fn process(&self, tokens: &mut Vec<TokenId>) {
-        if tokens.is_empty() {
+        self.process_tokens(tokens, 0, |_| {});
+    }
+
+    /// Processes the tokens after tokenization, calling `insert` to insert additional tokens before padding.
+    ///
+    /// `reserved` is the number of tokens inserted by `insert` and is subtracted from the truncation length.
+    #[inline(always)]
+    pub(crate) fn process_tokens<T: ProcessedToken>(
+        &self, tokens: &mut Vec<T>, reserved: usize, insert: impl FnOnce(&mut Vec<T>),
+    ) {
+        self.process_tokens_with(tokens, reserved, true, insert);
+    }
+
+    /// Processes the tokens after tokenization like [`process_tokens`](Self::process_tokens), but skips the padding steps.
+    #[inline(always)]
+    pub(crate) fn process_tokens_unpadded<T: ProcessedToken>(
+        &self, tokens: &mut Vec<T>, reserved: usize, insert: impl FnOnce(&mut Vec<T>),
+    ) {
+        self.process_tokens_with(tokens, reserved, false, insert);
+    }
+
+    #[inline(never)]
+    fn process_tokens_with<T: ProcessedToken>(
+        &self, tokens: &mut Vec<T>, reserved: usize, pad: bool, insert: impl FnOnce(&mut Vec<T>),
+    ) {
+        if tokens.is_empty() && reserved == 0 {
             return;
         }
+        let mut insert = Some(insert);
         for processing in &self.processing {
-            processing.process(tokens);
+            if let Processing::Pad { .. } = processing {
+                if let Some(insert) = insert.take() {
+                    insert(tokens);
+                }
+                if !pad {
+                    continue;
+                }
+            }
+            let reserved = if insert.is_some() { reserved } else { 0 };
+            processing.process_tokens(tokens, reserved);
         }
+        if let Some(insert) = insert.take() {
+            insert(tokens);
+        }
+    }
+
+    /// Returns the token id, length, stride and direction of the first padding step.
+    #[inline(always)]
+    pub(crate) fn padding(&self) -> Option<(TokenId, usize, usize, ProcessingDirection)> {
+        self.processing.iter().find_map(|processing| match processing {
+            Processing::Pad {
+                id,
+                length,
+                stride,
+                direction,
+            } => Some((*id, *length as usize, *stride as usize, *direction)),
+            _ => None,
+        })
+    }
+
+    /// Processes the tokens of a pair of sequences after tokenization, calling `combine` to join the sequences before padding.
+    ///
+    /// `reserved` is the number of tokens inserted by `combine` and is subtracted from the truncation length.
+    #[inline(never)]
+    pub(crate) fn process_pair_tokens<T: ProcessedToken>(
+        &self, first: Vec<T>, second: Vec<T>, reserved: usize,
+        combine: impl FnOnce(Vec<T>, Vec<T>) -> Vec<T>,
+    ) -> Vec<T> {
+        let mut pending = Some((first, second, combine));
+        let mut tokens = Vec::new();
+        for processing in &self.processing {
+            if let Processing::Pad { .. } = processing
+                && let Some((first, second, combine)) = pending.take()
+            {
+                tokens = combine(first, second);
+            }
+            if let Some((first, second, _)) = &mut pending {
+                processing.process_pair_tokens(first, second, reserved);
+            } else {
+                processing.process_tokens(&mut tokens, 0);
+            }
+        }
+        if let Some((first, second, combine)) = pending {
+            tokens = combine(first, second);
+        }
+        tokens
     }
 
     /// Postprocesses the bytes after detokenization.
@@ -186,4 +316,29 @@ impl Configuration {
             decoding.decode(tokens);
         }
     }
+
+    /// Postprocesses the bytes after detokenization like [`decode`](Self::decode) and maps the given `(start, end)` byte ranges
+    /// in the bytes to the ranges in the output.
+    #[inline(never)]
+    pub(crate) fn decode_ranges(&self, tokens: &mut Vec<u8>, ranges: &mut [(usize, usize)]) {
+        if tokens.is_empty() {
+            return;
+        }
+        for decoding in &self.decoding {
+            decoding.decode_ranges(tokens, ranges);
+        }
+    }
+
+    /// Postprocesses the bytes of a single token with the replacement steps of the decoding configuration.
+    #[inline(never)]
+    pub(crate) fn decode_token(&self, token: &mut Vec<u8>) {
+        if token.is_empty() {
+            return;
+        }
+        for decoding in &self.decoding {
+            if let Decoding::Replace { .. } = decoding {
+                decoding.decode(token);
+            }
+        }
+    }
 }
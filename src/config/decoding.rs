@@ -74,62 +74,213 @@ pub enum Decoding {
 }
 
 impl Decoding {
-    #[inline(never)]
+    #[inline(always)]
     pub fn decode(&self, text: &mut Vec<u8>) {
+        self.decode_ranges(text, &mut []);
+    }
+
+    /// Decodes the text and maps the given `(start, end)` byte ranges in the text to the ranges in the output.
+    ///
+    /// Inserted characters are not part of any range. Replaced text is aligned by the common prefix and suffix of the match
+    /// and its replacement, and the changed part is added to the range containing its start. The ranges must be sorted and not overlap.
+    #[inline(never)]
+    pub(crate) fn decode_ranges(&self, text: &mut Vec<u8>, ranges: &mut [(usize, usize)]) {
         use Decoding::*;
-        match self {
+        let edits = match self {
             Extend {
                 character,
                 left,
                 right,
                 pad,
             } => {
-                decode_extend(text, *character, *left, *right, *pad);
+                let (left, right) = extend_counts(text, *character, *left, *right, *pad);
+                let mut buffer = [0u8; 4];
+                let buffer = character.encode_utf8(&mut buffer).as_bytes();
+                let mut edits = Vec::new();
+                if left > 0 {
+                    edits.push((0, 0, buffer.repeat(left)));
+                }
+                if right > 0 {
+                    edits.push((text.len(), text.len(), buffer.repeat(right)));
+                }
+                edits
             }
             Strip {
                 character,
                 left,
                 right,
             } => {
-                decode_strip(text, *character, *left, *right);
+                let (start, end) = strip_counts(text, *character, *left, *right);
+                let mut edits = Vec::new();
+                if start > 0 {
+                    edits.push((0, start, Vec::new()));
+                }
+                if end > 0 {
+                    edits.push((text.len() - end, text.len(), Vec::new()));
+                }
+                edits
             }
             Collapse { character } => {
-                decode_collapse(text, *character);
+                let mut buffer = [0u8; 4];
+                let mut last = None;
+                let mut edits = Vec::new();
+                for (start, end, c) in text.char_indices() {
+                    if c == *character {
+                        if Some(c) == last {
+                            edits.push((start, end, Vec::new()));
+                            continue;
+                        }
+                        last = Some(c);
+                    } else {
+                        last = None;
+                    }
+                    let bytes = c.encode_utf8(&mut buffer).as_bytes();
+                    if &text[start..end] != bytes {
+                        edits.push((start, end, bytes.to_vec()));
+                    }
+                }
+                edits
+            }
+            Replace {
+                pattern: DecodingReplacePattern::Regex(regex),
+                replacement,
+            } => {
+                apply_edits(text, &lossy_edits(text), ranges);
+                let text = text.to_str_lossy();
+                let edits = regex.replacements(&text, replacement).into_iter();
+                edits
+                    .map(|(start, end, replacement)| (start, end, replacement.into_bytes()))
+                    .collect()
             }
             Replace {
                 pattern,
                 replacement,
             } => {
-                decode_replace(text, pattern, replacement);
+                let mut buffer = [0u8; 4];
+                let pattern = match pattern {
+                    DecodingReplacePattern::Character(character) => {
+                        character.encode_utf8(&mut buffer).as_bytes()
+                    }
+                    DecodingReplacePattern::String(pattern) => pattern.as_bytes(),
+                    DecodingReplacePattern::Regex(_) => unreachable!(),
+                };
+                text.find_iter(pattern)
+                    .map(|start| (start, start + pattern.len(), replacement.as_bytes().to_vec()))
+                    .collect()
             }
-        }
+        };
+        apply_edits(text, &edits, ranges);
     }
 }
 
+/// Replacement of the bytes in `start..end` with the given bytes.
+type Edit = (usize, usize, Vec<u8>);
+
+/// Applies the sorted and non-overlapping edits to the text and maps the given ranges in the text to the ranges in the output.
 #[inline(never)]
-fn decode_extend(text: &mut Vec<u8>, character: char, left: u32, right: u32, pad: bool) {
-    let mut buffer = [0u8; 4];
-    let buffer = character.encode_utf8(&mut buffer).as_bytes();
-    if left > 0 {
-        let mut left = left as usize;
-        if pad {
-            let leading = text.chars().take(left).take_while(|&c| c == character).count();
-            left = left.saturating_sub(leading);
-        }
-        text.splice(..0, core::iter::repeat_n(buffer, left).flatten().copied());
+fn apply_edits(text: &mut Vec<u8>, edits: &[Edit], ranges: &mut [(usize, usize)]) {
+    if edits.is_empty() {
+        return;
     }
-    if right > 0 {
-        let mut right = right as usize;
-        if pad {
-            let trailing = text.chars().rev().take(right).take_while(|&c| c == character).count();
-            right = right.saturating_sub(trailing);
+    map_offsets(text, edits, ranges.iter_mut().map(|(start, _)| start), false);
+    map_offsets(text, edits, ranges.iter_mut().map(|(_, end)| end), true);
+    for (start, end) in ranges.iter_mut() {
+        *start = (*start).min(*end);
+    }
+    let mut output = Vec::with_capacity(text.len());
+    let mut position = 0;
+    for (start, end, replacement) in edits {
+        output.extend_from_slice(&text[position..*start]);
+        output.extend_from_slice(replacement);
+        position = *end;
+    }
+    output.extend_from_slice(&text[position..]);
+    *text = output;
+}
+
+/// Maps the sorted offsets in the text to the offsets in the output of the edits.
+///
+/// Offsets inside the common prefix or suffix of a replaced range and its replacement keep their position relative to it,
+/// other offsets inside a replaced range are moved to the end of the changed part of the replacement.
+/// Start offsets at an insertion are moved after the inserted bytes, end offsets stay before them.
+#[inline(always)]
+fn map_offsets<'a>(
+    text: &[u8], edits: &[Edit], offsets: impl Iterator<Item = &'a mut usize>, end: bool,
+) {
+    let mut index = 0;
+    let mut delta = 0isize;
+    for offset in offsets {
+        while let Some((start, stop, replacement)) = edits.get(index) {
+            if *offset < *stop || (end && *offset <= *start) {
+                break;
+            }
+            delta += replacement.len() as isize - (stop - start) as isize;
+            index += 1;
+        }
+        match edits.get(index) {
+            Some((start, stop, replacement)) if *start < *offset && *offset < *stop => {
+                let replaced = &text[*start..*stop];
+                let prefix = replaced.iter().zip(replacement).take_while(|(a, b)| a == b).count();
+                let suffix = replaced[prefix..]
+                    .iter()
+                    .rev()
+                    .zip(replacement[prefix..].iter().rev())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let output = (*start as isize + delta) as usize;
+                *offset = if *offset - start <= prefix {
+                    output + (*offset - start)
+                } else if *stop - *offset <= suffix {
+                    output + replacement.len() - (*stop - *offset)
+                } else {
+                    output + replacement.len() - suffix
+                };
+            }
+            _ => *offset = (*offset as isize + delta) as usize,
         }
-        text.extend(core::iter::repeat_n(buffer, right).flatten().copied());
     }
 }
 
+/// Returns the edits replacing invalid UTF-8 sequences with `U+FFFD` like [`ByteSlice::to_str_lossy`].
 #[inline(never)]
-fn decode_strip(text: &mut Vec<u8>, character: char, mut left: u32, mut right: u32) {
+fn lossy_edits(text: &[u8]) -> Vec<Edit> {
+    let mut buffer = [0u8; 4];
+    let replacement = char::REPLACEMENT_CHARACTER.encode_utf8(&mut buffer).as_bytes();
+    let mut edits = Vec::new();
+    let mut position = 0;
+    while let Err(error) = core::str::from_utf8(&text[position..]) {
+        let start = position + error.valid_up_to();
+        let end = error.error_len().map_or(text.len(), |len| start + len);
+        edits.push((start, end, replacement.to_vec()));
+        position = end;
+    }
+    edits
+}
+
+/// Returns the number of characters added to the beginning and end by [`Decoding::Extend`].
+#[inline(always)]
+pub(super) fn extend_counts(
+    text: &[u8], character: char, left: u32, right: u32, pad: bool,
+) -> (usize, usize) {
+    let mut left = left as usize;
+    let mut right = right as usize;
+    if pad && left > 0 {
+        let leading = text.chars().take(left).take_while(|&c| c == character).count();
+        left = left.saturating_sub(leading);
+    }
+    if pad && right > 0 {
+        let text = text.chars().rev().chain(core::iter::repeat_n(character, left));
+        let trailing = text.take(right).take_while(|&c| c == character).count();
+        right = right.saturating_sub(trailing);
+    }
+    (left, right)
+}
+
+/// Returns the number of bytes removed from the beginning and end by [`Decoding::Strip`].
+#[inline(always)]
+pub(super) fn strip_counts(
+    text: &[u8], character: char, mut left: u32, mut right: u32,
+) -> (usize, usize) {
     let mut slice_start = 0;
     let mut slice_end = 0;
     if left > 0 {
@@ -150,50 +301,7 @@ fn decode_strip(text: &mut Vec<u8>, character: char, mut left: u32, mut right: u
             right -= 1;
         }
     }
-    if slice_start > 0 {
-        text.drain(..slice_start);
-    }
-    if slice_end > 0 {
-        let len = text.len();
-        text.drain(len - slice_end..);
-    }
-}
-
-#[inline(never)]
-fn decode_collapse(text: &mut Vec<u8>, character: char) {
-    let mut buffer = [0u8; 4];
-    let mut last = None;
-    let mut output = Vec::with_capacity(text.len());
-    for c in text.chars() {
-        if c == character {
-            if Some(c) == last {
-                continue;
-            }
-            last = Some(c);
-        } else {
-            last = None;
-        }
-        output.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
-    }
-    *text = output;
-}
-
-#[inline(never)]
-fn decode_replace(text: &mut Vec<u8>, pattern: &DecodingReplacePattern, replacement: &str) {
-    match pattern {
-        DecodingReplacePattern::Character(character) => {
-            let mut buffer = [0u8; 4];
-            let bytes = character.encode_utf8(&mut buffer).as_bytes();
-            *text = text.replace(bytes, replacement.as_bytes());
-        }
-        DecodingReplacePattern::String(pattern) => {
-            let bytes = pattern.as_bytes();
-            *text = text.replace(bytes, replacement.as_bytes());
-        }
-        DecodingReplacePattern::Regex(regex) => {
-            *text = regex.replace_all(&text.to_str_lossy(), replacement).into();
-        }
-    }
+    (slice_start, slice_end)
 }
 
 #[cfg(test)]
@@ -255,4 +363,38 @@ mod tests {
         decoding.decode(&mut text);
         assert_eq!(text, Vec::from(b"aaaa"));
     }
+
+    #[test]
+    fn test_decoding_ranges() {
+        let decodings = [
+            Decoding::Replace {
+                pattern:     '▁'.into(),
+                replacement: " ".to_owned(),
+            },
+            Decoding::Strip {
+                character: ' ',
+                left:      1,
+                right:     0,
+            },
+            Decoding::Collapse { character: 'b' },
+            Decoding::Extend {
+                character: '!',
+                left:      1,
+                right:     1,
+                pad:       false,
+            },
+        ];
+        let mut text = "▁abb▁bbc".as_bytes().to_vec();
+        let mut ranges = [(0, 5), (5, 6), (6, 10), (10, 12), (12, 12)];
+        for decoding in &decodings {
+            decoding.decode_ranges(&mut text, &mut ranges);
+        }
+        let mut expected = "▁abb▁bbc".as_bytes().to_vec();
+        for decoding in &decodings {
+            decoding.decode(&mut expected);
+        }
+        assert_eq!(text, expected);
+        assert_eq!(text, Vec::from(b"!ab bc!"));
+        assert_eq!(ranges, [(1, 3), (3, 3), (3, 5), (5, 6), (6, 6)]);
+    }
 }
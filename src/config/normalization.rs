@@ -5,11 +5,12 @@ use core::ops::Range;
 use alloc::borrow::Cow;
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
-use once_cell::race::OnceBox;
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
 
+use super::decoding::{extend_counts, strip_counts};
 use crate::{CharsMap, Regex};
 
 /// Unicode normalization scheme.
@@ -114,53 +115,168 @@ pub enum Normalization {
     },
 }
 
-impl Normalization {
+/// Alignment of a normalized text with the input text.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Alignment {
+    /// Byte offset of each character in the normalized text with the byte range in the input text it was normalized from.
+    pub(crate) chars: Vec<(usize, usize, usize)>,
+    /// Byte offset of the end of the text in the input text.
+    pub(crate) end:   usize,
+}
+impl Alignment {
+    /// Creates the alignment of the unchanged text at byte offset `offset` in the input text.
     #[inline(never)]
-    pub fn normalize(&self, text: &mut Cow<str>, position: Range<usize>) {
-        use Normalization::*;
-        match self {
-            Unicode { scheme } => {
-                normalize_unicode(text, *scheme);
-            }
-            NMT => {
-                normalize_nmt(text);
+    pub(crate) fn new(text: &str, offset: usize) -> Self {
+        Self {
+            chars: text
+                .char_indices()
+                .map(|(i, c)| (i, offset + i, offset + i + c.len_utf8()))
+                .collect(),
+            end:   offset + text.len(),
+        }
+    }
+
+    /// Maps the characters of the text to the characters of the output of the edits.
+    ///
+    /// Characters outside of the edits keep their range. Inserted characters map to an empty range at the start of the following character.
+    /// Replaced characters map one to one if the replacement has the same number of characters, or else to the range of all replaced characters.
+    #[inline(never)]
+    fn apply(&mut self, edits: &[Edit]) {
+        let mut chars = Vec::with_capacity(self.chars.len());
+        let mut index = 0;
+        let mut delta = 0isize;
+        for (start, end, replacement) in edits {
+            while let Some(&(offset, source_start, source_end)) = self.chars.get(index) {
+                if offset >= *start {
+                    break;
+                }
+                chars.push(((offset as isize + delta) as usize, source_start, source_end));
+                index += 1;
             }
-            CaseFold { upper } => {
-                normalize_casefold(text, *upper);
+            let first = index;
+            while self.chars.get(index).is_some_and(|&(offset, _, _)| offset < *end) {
+                index += 1;
             }
-            Append { append } => {
-                normalize_append(text, append);
+            let replaced = &self.chars[first..index];
+            let output = (*start as isize + delta) as usize;
+            let count = replacement.chars().count();
+            for (k, (offset, _)) in replacement.char_indices().enumerate() {
+                let (source_start, source_end) = match replaced {
+                    [] => {
+                        let position = self.chars.get(index).map_or(self.end, |&(_, s, _)| s);
+                        (position, position)
+                    }
+                    _ if replaced.len() == count => (replaced[k].1, replaced[k].2),
+                    [(_, first, _), .., (_, _, last)] => (*first, *last),
+                    [(_, first, last)] => (*first, *last),
+                };
+                chars.push((output + offset, source_start, source_end));
             }
-            Prepend { prepend } => {
-                normalize_prepend(text, prepend);
+            delta += replacement.len() as isize - (end - start) as isize;
+        }
+        for &(offset, source_start, source_end) in &self.chars[index..] {
+            chars.push(((offset as isize + delta) as usize, source_start, source_end));
+        }
+        self.chars = chars;
+    }
+}
+
+/// Replacement of the bytes in `start..end` with the given text.
+type Edit = (usize, usize, String);
+
+/// Applies the sorted and non-overlapping edits to the text and maps the alignment to the output.
+#[inline(never)]
+fn apply_edits(text: &mut Cow<str>, edits: &[Edit], alignment: Option<&mut Alignment>) {
+    if edits.is_empty() {
+        return;
+    }
+    if let Some(alignment) = alignment {
+        alignment.apply(edits);
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut position = 0;
+    for (start, end, replacement) in edits {
+        output.push_str(&text[position..*start]);
+        output.push_str(replacement);
+        position = *end;
+    }
+    output.push_str(&text[position..]);
+    *text = Cow::Owned(output);
+}
+
+impl Normalization {
+    #[inline(always)]
+    pub fn normalize(&self, text: &mut Cow<str>, position: Range<usize>) {
+        self.normalize_aligned(text, position, None);
+    }
+
+    /// Normalizes the text like [`normalize`](Self::normalize) and maps the alignment of the text to the output.
+    #[inline(never)]
+    pub(crate) fn normalize_aligned(
+        &self, text: &mut Cow<str>, position: Range<usize>, alignment: Option<&mut Alignment>,
+    ) {
+        use Normalization::*;
+        let edits = match self {
+            Unicode { scheme } => unicode_edits(text, *scheme),
+            NMT => nmt_edits(text),
+            CaseFold { upper } => casefold_edits(text, *upper),
+            Append { append } if !append.is_empty() => {
+                Vec::from([(text.len(), text.len(), append.clone())])
             }
+            Prepend { prepend } if !prepend.is_empty() => Vec::from([(0, 0, prepend.clone())]),
+            Append { .. } | Prepend { .. } => Vec::new(),
             Extend {
                 character,
                 left,
                 right,
                 pad,
             } => {
-                normalize_extend(text, *character, *left, *right, *pad);
+                let (left, right) = extend_counts(text.as_bytes(), *character, *left, *right, *pad);
+                let mut edits = Vec::new();
+                if left > 0 {
+                    edits.push((0, 0, character.to_string().repeat(left)));
+                }
+                if right > 0 {
+                    edits.push((text.len(), text.len(), character.to_string().repeat(right)));
+                }
+                edits
             }
             Strip {
                 character,
                 left,
                 right,
             } => {
-                normalize_strip(text, *character, *left, *right);
+                let (start, end) = strip_counts(text.as_bytes(), *character, *left, *right);
+                let mut edits = Vec::new();
+                if start > 0 {
+                    edits.push((0, start, String::new()));
+                }
+                if end > 0 {
+                    edits.push((text.len() - end, text.len(), String::new()));
+                }
+                edits
             }
             Collapse { character } => {
-                normalize_collapse(text, *character);
+                let mut last = None;
+                let mut edits = Vec::new();
+                for (start, c) in text.char_indices() {
+                    if c == *character {
+                        if Some(c) == last {
+                            edits.push((start, start + c.len_utf8(), String::new()));
+                            continue;
+                        }
+                        last = Some(c);
+                    } else {
+                        last = None;
+                    }
+                }
+                edits
             }
             Replace {
                 pattern,
                 replacement,
-            } => {
-                normalize_replace(text, pattern, replacement);
-            }
-            CharsMap { map } => {
-                normalize_charsmap(text, map);
-            }
+            } => replace_edits(text, pattern, replacement),
+            CharsMap { map } => charsmap_edits(text, map),
             Conditional {
                 condition,
                 normalization,
@@ -169,176 +285,164 @@ impl Normalization {
                     NormalizationCondition::StartOfText => position.start == 0,
                     NormalizationCondition::EndOfText => position.end == usize::MAX,
                 } {
-                    normalization.normalize(text, position);
+                    normalization.normalize_aligned(text, position, alignment);
                 }
+                return;
             }
-        }
+        };
+        apply_edits(text, &edits, alignment);
     }
 }
 
+/// Returns the edits normalizing the text with the given scheme.
+///
+/// The text is normalized in segments starting at characters that do not compose with the preceding characters,
+/// segments are merged with the following segments until their normalization matches the normalization of the whole text.
 #[cfg(feature = "normalization-unicode")]
 #[inline(never)]
-fn normalize_unicode(text: &mut Cow<str>, scheme: UnicodeNormalization) {
+fn unicode_edits(text: &str, scheme: UnicodeNormalization) -> Vec<Edit> {
     use UnicodeNormalization::*;
     use unicode_normalization::UnicodeNormalization as _;
-    match scheme {
-        NFC => {
-            *text.to_mut() = text.nfc().collect();
+    use unicode_normalization::char::canonical_combining_class;
+    let normalize = |text: &str| -> String {
+        match scheme {
+            NFC => text.nfc().collect(),
+            NFD => text.nfd().collect(),
+            NFKC => text.nfkc().collect(),
+            NFKD => text.nfkd().collect(),
         }
-        NFD => {
-            *text.to_mut() = text.nfd().collect();
-        }
-        NFKC => {
-            *text.to_mut() = text.nfkc().collect();
+    };
+    let normalized = match scheme {
+        NFC => unicode_normalization::is_nfc(text),
+        NFD => unicode_normalization::is_nfd(text),
+        NFKC => unicode_normalization::is_nfkc(text),
+        NFKD => unicode_normalization::is_nfkd(text),
+    };
+    if normalized {
+        return Vec::new();
+    }
+    let normalized = normalize(text);
+    let boundaries = text
+        .char_indices()
+        .skip(1)
+        .filter(|&(_, c)| {
+            // hangul vowel and trailing jamo compose with the preceding starter
+            canonical_combining_class(c) == 0
+                && !matches!(c, '\u{1161}'..='\u{1175}' | '\u{11A8}'..='\u{11C2}')
+        })
+        .map(|(i, _)| i)
+        .chain(core::iter::once(text.len()));
+    let mut edits = Vec::new();
+    let (mut start, mut position) = (0, 0);
+    for end in boundaries {
+        let mut segment = normalize(&text[start..end]);
+        if !normalized[position..].starts_with(&segment) {
+            if end < text.len() {
+                continue;
+            }
+            segment = normalized[position..].to_string();
         }
-        NFKD => {
-            *text.to_mut() = text.nfkd().collect();
+        position += segment.len();
+        if segment != text[start..end] {
+            edits.push((start, end, segment));
         }
+        start = end;
     }
+    edits
 }
 #[cfg(not(feature = "normalization-unicode"))]
 #[inline(never)]
-fn normalize_unicode(_text: &mut Cow<str>, _scheme: UnicodeNormalization) {
+fn unicode_edits(_text: &str, _scheme: UnicodeNormalization) -> Vec<Edit> {
     log::warn!("Unicode normalization must be enabled for Unicode normalization");
+    Vec::new()
 }
 
+/// Returns the edits removing control characters and replacing whitespace characters with spaces.
 #[inline(never)]
-fn normalize_nmt(text: &mut Cow<str>) {
-    text.to_mut()
-        .retain(|c| !matches!(c, '\u{1}'..='\u{8}' | '\u{e}'..='\u{1f}' | '\u{b}' | '\u{7f}' | '\u{8f}' | '\u{9f}'));
-    static NMT_REGEX_SPACE: OnceBox<Regex> = const { OnceBox::new() };
-    let replacer_space = NMT_REGEX_SPACE.get_or_init(|| {
-            Box::new(Regex::new("[\u{0}\u{a}\u{c}\u{d}\u{1680}\u{200B}-\u{200F}\u{2028}\u{2029}\u{2581}\u{feff}\u{fffd}]")
-                .unwrap())
-    });
-    *text.to_mut() = replacer_space.replace_all(text, " ");
-}
-
-#[inline(never)]
-fn normalize_casefold(text: &mut Cow<str>, upper: bool) {
-    if upper {
-        *text.to_mut() = text.to_uppercase();
-    } else {
-        *text.to_mut() = text.to_lowercase();
-    }
-}
-
-#[inline(never)]
-fn normalize_append(text: &mut Cow<str>, append: &str) {
-    text.to_mut().push_str(append);
-}
-
-#[inline(never)]
-fn normalize_prepend(text: &mut Cow<str>, prepend: &str) {
-    text.to_mut().insert_str(0, prepend);
-}
-
-#[inline(never)]
-fn normalize_extend(text: &mut Cow<str>, character: char, left: u32, right: u32, pad: bool) {
-    let mut buffer = [0u8; 4];
-    let buffer = character.encode_utf8(&mut buffer).as_bytes();
-    if left > 0 {
-        let mut left = left as usize;
-        if pad {
-            let leading = text.chars().take(left).take_while(|&c| c == character).count();
-            left = left.saturating_sub(leading);
-        }
-        unsafe {
-            text.to_mut()
-                .as_mut_vec()
-                .splice(..0, core::iter::repeat_n(buffer, left).flatten().copied());
-        };
-    }
-    if right > 0 {
-        let mut right = right as usize;
-        if pad {
-            let trailing = text.chars().rev().take(right).take_while(|&c| c == character).count();
-            right = right.saturating_sub(trailing);
+fn nmt_edits(text: &str) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    for (start, c) in text.char_indices() {
+        let end = start + c.len_utf8();
+        match c {
+            '\u{1}'..='\u{8}' | '\u{e}'..='\u{1f}' | '\u{b}' | '\u{7f}' | '\u{8f}' | '\u{9f}' => {
+                edits.push((start, end, String::new()));
+            }
+            '\u{0}'
+            | '\u{a}'
+            | '\u{c}'
+            | '\u{d}'
+            | '\u{1680}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{2028}'
+            | '\u{2029}'
+            | '\u{2581}'
+            | '\u{feff}'
+            | '\u{fffd}' => {
+                edits.push((start, end, " ".to_string()));
+            }
+            _ => {}
         }
-        unsafe {
-            text.to_mut()
-                .as_mut_vec()
-                .extend(core::iter::repeat_n(buffer, right).flatten().copied());
-        };
     }
+    edits
 }
 
+/// Returns the edits converting the text to upper or lower case.
 #[inline(never)]
-fn normalize_strip(text: &mut Cow<str>, character: char, mut left: u32, mut right: u32) {
-    let mut slice_start = 0;
-    let mut slice_end = 0;
-    if left > 0 {
-        for c in text[..].chars() {
-            if c != character || left == 0 {
-                break;
-            }
-            slice_start += c.len_utf8();
-            left -= 1;
-        }
+fn casefold_edits(text: &str, upper: bool) -> Vec<Edit> {
+    let folded = if upper {
+        text.to_uppercase()
+    } else {
+        text.to_lowercase()
+    };
+    if folded == text {
+        return Vec::new();
     }
-    if right > 0 {
-        for c in text[slice_start..].chars().rev() {
-            if c != character || right == 0 {
-                break;
-            }
-            slice_end += c.len_utf8();
-            right -= 1;
+    let mut edits = Vec::new();
+    let mut position = 0;
+    for (start, c) in text.char_indices() {
+        // the length of a folded character does not depend on its context
+        let len = if upper {
+            c.to_uppercase().map(char::len_utf8).sum::<usize>()
+        } else {
+            c.to_lowercase().map(char::len_utf8).sum::<usize>()
+        };
+        let end = start + c.len_utf8();
+        if folded[position..position + len] != text[start..end] {
+            edits.push((start, end, folded[position..position + len].to_string()));
         }
+        position += len;
     }
-    if slice_start > 0 {
-        text.to_mut().drain(..slice_start);
-    }
-    if slice_end > 0 {
-        let len = text.len();
-        text.to_mut().drain(len - slice_end..);
-    }
+    edits
 }
 
+/// Returns the edits replacing the matches of the pattern.
 #[inline(never)]
-fn normalize_collapse(text: &mut Cow<str>, character: char) {
-    let mut last = None;
-    *text.to_mut() = text
-        .chars()
-        .filter(|&c| {
-            if c == character {
-                if Some(c) == last {
-                    return false;
-                }
-                last = Some(c);
-            } else {
-                last = None;
-            }
-            true
-        })
-        .collect();
-}
-
-#[inline(never)]
-fn normalize_replace(
-    text: &mut Cow<str>, pattern: &NormalizationReplacePattern, replacement: &str,
-) {
+fn replace_edits(
+    text: &str, pattern: &NormalizationReplacePattern, replacement: &str,
+) -> Vec<Edit> {
+    let edit =
+        |(start, matched): (usize, &str)| (start, start + matched.len(), replacement.to_string());
     match pattern {
         NormalizationReplacePattern::Character(character) => {
-            *text.to_mut() = text.replace(*character, replacement);
+            text.match_indices(*character).map(edit).collect()
         }
         NormalizationReplacePattern::String(pattern) => {
-            *text.to_mut() = text.replace(pattern, replacement);
-        }
-        NormalizationReplacePattern::Regex(pattern) => {
-            *text.to_mut() = pattern.replace_all(text, replacement);
+            text.match_indices(pattern.as_str()).map(edit).collect()
         }
+        NormalizationReplacePattern::Regex(pattern) => pattern.replacements(text, replacement),
     }
 }
 
 #[cfg(feature = "normalization-charsmap")]
 #[inline(never)]
-fn normalize_charsmap(text: &mut Cow<str>, map: &CharsMap) {
-    *text = map.normalize(text).into();
+fn charsmap_edits(text: &str, map: &CharsMap) -> Vec<Edit> {
+    map.edits(text)
 }
 #[cfg(not(feature = "normalization-charsmap"))]
 #[inline(never)]
-fn normalize_charsmap(_text: &mut Cow<str>, _map: &CharsMap) {
+fn charsmap_edits(_text: &str, _map: &CharsMap) -> Vec<Edit> {
     log::warn!("CharsMap normalization must be enabled for CharsMap normalization");
+    Vec::new()
 }
 
 #[cfg(test)]
@@ -43,9 +43,72 @@ pub enum Processing {
     },
 }
 
+/// Token processed by the post-tokenization output processing.
+pub(crate) trait ProcessedToken: Copy {
+    /// Returns the token id.
+    fn id(&self) -> TokenId;
+    /// Returns a token with the given id inserted during processing.
+    fn inserted(id: TokenId) -> Self;
+}
+impl ProcessedToken for TokenId {
+    #[inline(always)]
+    fn id(&self) -> TokenId {
+        *self
+    }
+
+    #[inline(always)]
+    fn inserted(id: TokenId) -> Self {
+        id
+    }
+}
+/// Token with its byte range in the input. Inserted tokens have an empty range.
+impl ProcessedToken for (TokenId, (usize, usize)) {
+    #[inline(always)]
+    fn id(&self) -> TokenId {
+        self.0
+    }
+
+    #[inline(always)]
+    fn inserted(id: TokenId) -> Self {
+        (id, (0, 0))
+    }
+}
+
+/// Token with a flag set for tokens inserted during processing.
+impl ProcessedToken for (TokenId, bool) {
+    #[inline(always)]
+    fn id(&self) -> TokenId {
+        self.0
+    }
+
+    #[inline(always)]
+    fn inserted(id: TokenId) -> Self {
+        (id, true)
+    }
+}
+
+/// Token with its byte range in the input and its type id. Inserted tokens have an empty range and type id 0.
+impl ProcessedToken for (TokenId, (usize, usize), u32) {
+    #[inline(always)]
+    fn id(&self) -> TokenId {
+        self.0
+    }
+
+    #[inline(always)]
+    fn inserted(id: TokenId) -> Self {
+        (id, (0, 0), 0)
+    }
+}
+
 impl Processing {
-    #[inline(never)]
+    #[inline(always)]
     pub fn process(&self, tokens: &mut Vec<TokenId>) {
+        self.process_tokens(tokens, 0);
+    }
+
+    /// Processes the tokens, reducing the truncation length by `reserved` tokens.
+    #[inline(never)]
+    pub(crate) fn process_tokens<T: ProcessedToken>(&self, tokens: &mut Vec<T>, reserved: usize) {
         use Processing::*;
         match self {
             Strip { id, left, right } => {
@@ -67,19 +130,45 @@ impl Processing {
                 stride,
                 direction,
             } => {
-                process_truncate(tokens, *length as _, *stride as _, *direction);
+                let length = (*length as usize).saturating_sub(reserved);
+                process_truncate(tokens, length, *stride as _, *direction);
             }
         }
     }
 }
 
+impl Processing {
+    /// Processes the tokens of a pair of sequences, reducing the truncation length by `reserved` tokens.
+    ///
+    /// Truncation removes tokens from the longer sequence first.
+    #[inline(never)]
+    pub(crate) fn process_pair_tokens<T: ProcessedToken>(
+        &self, first: &mut Vec<T>, second: &mut Vec<T>, reserved: usize,
+    ) {
+        if let Processing::Truncate {
+            length,
+            stride,
+            direction,
+        } = self
+        {
+            let length = (*length as usize).saturating_sub(reserved);
+            process_truncate_pair(first, second, length, *stride as _, *direction);
+        } else {
+            self.process_tokens(first, 0);
+            self.process_tokens(second, 0);
+        }
+    }
+}
+
 #[inline(never)]
-fn process_strip(tokens: &mut Vec<TokenId>, id: TokenId, mut left: u32, mut right: u32) {
+fn process_strip<T: ProcessedToken>(
+    tokens: &mut Vec<T>, id: TokenId, mut left: u32, mut right: u32,
+) {
     let mut slice_start = 0;
     let mut slice_end = 0;
     if left > 0 {
-        for &c in tokens.iter() {
-            if c != id || left == 0 {
+        for c in tokens.iter() {
+            if c.id() != id || left == 0 {
                 break;
             }
             slice_start += 1;
@@ -87,8 +176,8 @@ fn process_strip(tokens: &mut Vec<TokenId>, id: TokenId, mut left: u32, mut righ
         }
     }
     if right > 0 {
-        for &c in tokens.iter().rev() {
-            if c != id || right == 0 {
+        for c in tokens.iter().rev() {
+            if c.id() != id || right == 0 {
                 break;
             }
             slice_end += 1;
@@ -105,9 +194,10 @@ fn process_strip(tokens: &mut Vec<TokenId>, id: TokenId, mut left: u32, mut righ
 }
 
 #[inline(never)]
-fn process_collapse(tokens: &mut Vec<TokenId>, id: TokenId) {
+fn process_collapse<T: ProcessedToken>(tokens: &mut Vec<T>, id: TokenId) {
     let mut last = None;
-    tokens.retain(|&token| {
+    tokens.retain(|token| {
+        let token = token.id();
         let keep = last != Some(token) || token != id;
         last = Some(token);
         keep
@@ -115,9 +205,8 @@ fn process_collapse(tokens: &mut Vec<TokenId>, id: TokenId) {
 }
 
 #[inline(never)]
-fn process_pad(
-    tokens: &mut Vec<TokenId>, id: TokenId, length: usize, stride: usize,
-    direction: ProcessingDirection,
+fn process_pad<T: ProcessedToken>(
+    tokens: &mut Vec<T>, id: TokenId, length: usize, stride: usize, direction: ProcessingDirection,
 ) {
     let len = tokens.len();
     if len >= length {
@@ -131,18 +220,18 @@ fn process_pad(
     if amount > 0 {
         match direction {
             ProcessingDirection::Left => {
-                tokens.splice(0..0, core::iter::repeat_n(id, amount));
+                tokens.splice(0..0, core::iter::repeat_n(T::inserted(id), amount));
             }
             ProcessingDirection::Right => {
-                tokens.resize(tokens.len() + amount, id);
+                tokens.resize(tokens.len() + amount, T::inserted(id));
             }
         }
     }
 }
 
 #[inline(never)]
-fn process_truncate(
-    tokens: &mut Vec<TokenId>, length: usize, stride: usize, direction: ProcessingDirection,
+fn process_truncate<T: ProcessedToken>(
+    tokens: &mut Vec<T>, length: usize, stride: usize, direction: ProcessingDirection,
 ) {
     let len = tokens.len();
     if len <= length {
@@ -165,6 +254,40 @@ fn process_truncate(
     }
 }
 
+#[inline(never)]
+fn process_truncate_pair<T: ProcessedToken>(
+    first: &mut Vec<T>, second: &mut Vec<T>, length: usize, stride: usize,
+    direction: ProcessingDirection,
+) {
+    let len = first.len() + second.len();
+    if len <= length {
+        return;
+    }
+    let amount = if stride > 0 && !(len - length).is_multiple_of(stride) {
+        (len - length) + (stride - (len - length) % stride)
+    } else {
+        len - length
+    };
+    let (mut first_len, mut second_len) = (first.len(), second.len());
+    for _ in 0..amount.min(len) {
+        if first_len > second_len {
+            first_len -= 1;
+        } else {
+            second_len -= 1;
+        }
+    }
+    for (tokens, keep) in [(first, first_len), (second, second_len)] {
+        match direction {
+            ProcessingDirection::Left => {
+                tokens.drain(0..tokens.len() - keep);
+            }
+            ProcessingDirection::Right => {
+                tokens.truncate(keep);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,4 +354,30 @@ mod tests {
         processing.process(&mut tokens);
         assert_eq!(tokens, Vec::from([1, 2, 3, 4]));
     }
+
+    #[test]
+    fn test_processing_truncate_reserved() {
+        let mut tokens = Vec::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let processing = Processing::Truncate {
+            length:    5,
+            stride:    0,
+            direction: ProcessingDirection::Right,
+        };
+        processing.process_tokens(&mut tokens, 2);
+        assert_eq!(tokens, Vec::from([1, 2, 3]));
+    }
+
+    #[test]
+    fn test_processing_truncate_pair() {
+        let mut first = Vec::from([1, 2, 3, 4, 5, 6]);
+        let mut second = Vec::from([7, 8, 9]);
+        let processing = Processing::Truncate {
+            length:    6,
+            stride:    0,
+            direction: ProcessingDirection::Right,
+        };
+        processing.process_pair_tokens(&mut first, &mut second, 1);
+        assert_eq!(first, Vec::from([1, 2, 3]));
+        assert_eq!(second, Vec::from([7, 8]));
+    }
 }
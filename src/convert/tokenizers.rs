@@ -19,8 +19,8 @@ use crate::convert::ConversionError;
 use crate::{
     Configuration, Decoding, Definition, Fallback, InsertionPosition, Kitoken, Metadata, Model,
     Normalization, NormalizationCondition, Processing, ProcessingDirection, Regex, Scores,
-    SpecialToken, SpecialTokenKind, SpecialVocab, Split, SplitBehavior, Template, Token,
-    TokenBytes, TokenId, UnicodeNormalization, Vocab,
+    SpecialToken, SpecialTokenKind, SpecialVocab, Split, SplitBehavior, Template, TemplateTypeIds,
+    Token, TokenBytes, TokenId, UnicodeNormalization, Vocab,
 };
 
 mod hf {
@@ -123,6 +123,12 @@ mod hf {
         pub byte_fallback: Option<bool>,
     }
 
+    #[derive(Deserialize, Debug, Clone, PartialEq)]
+    pub struct WordLevel {
+        pub unk_token: String,
+        pub vocab:     HashMap<String, u32>,
+    }
+
     #[derive(Deserialize, Debug, Clone, PartialEq)]
     #[serde(untagged)]
     #[allow(clippy::upper_case_acronyms)]
@@ -130,6 +136,7 @@ mod hf {
         BPE(BPE),
         WordPiece(WordPiece),
         Unigram(Unigram),
+        WordLevel(WordLevel),
     }
 
     #[derive(Deserialize, Debug, Clone, PartialEq)]
@@ -446,7 +453,7 @@ use hf::{AddedToken, Tokenizer};
 /// See the [tokenizers documentation](https://huggingface.co/docs/tokenizers) for more information.
 ///
 /// Tokenizers definitions can contain different model types, including `BPE`, `Unigram`, `WordPiece` and `WordLevel`.
-/// This function supports conversion of `BPE`, `Unigram` and `WordPiece` models.
+/// This function supports conversion of all of these model types.
 pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, ConversionError> {
     let data = data.as_ref();
 
@@ -773,6 +780,7 @@ pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, Conversi
     // Convert post-processors
     while let Some(post_processor) = post_processors.pop_front() {
         use hf::PostProcessor;
+        let roberta = matches!(post_processor, PostProcessor::RobertaProcessing { .. });
         match post_processor {
             PostProcessor::RobertaProcessing { sep, cls, .. }
             | PostProcessor::BertProcessing { sep, cls } => {
@@ -792,14 +800,37 @@ pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, Conversi
                     score:   0.0,
                     extract: true,
                 });
-                config.templates.push(Template {
-                    content:  sep.0,
-                    position: InsertionPosition::SequenceEnd,
-                });
-                config.templates.push(Template {
-                    content:  cls.0,
-                    position: InsertionPosition::SequenceStart,
-                });
+                if roberta {
+                    // `<s> A </s>` for single and `<s> A </s> </s> B </s>` for pair sequences,
+                    // with the second sequence starting at the separator between the sequences.
+                    config.templates.push(Template {
+                        content:  cls.0,
+                        position: InsertionPosition::SequenceStart,
+                    });
+                    config.templates.push(Template {
+                        content:  sep.0.clone(),
+                        position: InsertionPosition::SequenceContinuation,
+                    });
+                    config.templates.push(Template {
+                        content:  sep.0,
+                        position: InsertionPosition::SubSequenceEnd,
+                    });
+                    config.type_ids.sequence_continuation = 1;
+                } else {
+                    // `[CLS] A [SEP]` for single and `[CLS] A [SEP] B [SEP]` for pair sequences.
+                    config.templates.push(Template {
+                        content:  sep.0.clone(),
+                        position: InsertionPosition::SequenceEnd,
+                    });
+                    config.templates.push(Template {
+                        content:  cls.0,
+                        position: InsertionPosition::SequenceStart,
+                    });
+                    config.templates.push(Template {
+                        content:  sep.0,
+                        position: InsertionPosition::SequenceContinuation,
+                    });
+                }
             }
             PostProcessor::ByteLevel { .. } => {
                 if !decode_byte_chars {
@@ -861,13 +892,15 @@ pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, Conversi
                         After,
                     }
                     let mut segment = Segment::Before;
+                    let mut sequence_types = Vec::new();
                     let mut p0 = Vec::new();
                     let mut p1 = Vec::new();
                     let mut p2 = Vec::new();
                     for piece in pair.iter() {
                         use hf::TemplatePiece;
                         match piece {
-                            TemplatePiece::Sequence { .. } => {
+                            TemplatePiece::Sequence { type_id, .. } => {
+                                sequence_types.push(*type_id);
                                 segment = match segment {
                                     Segment::Before => Segment::Between,
                                     Segment::Between => Segment::After,
@@ -879,43 +912,71 @@ pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, Conversi
                                     }
                                 };
                             }
-                            TemplatePiece::SpecialToken { id, .. } => match segment {
-                                Segment::Before => p0.push(id.clone()),
-                                Segment::Between => p1.push(id.clone()),
-                                Segment::After => p2.push(id.clone()),
+                            TemplatePiece::SpecialToken { id, type_id } => match segment {
+                                Segment::Before => p0.push((id.clone(), *type_id)),
+                                Segment::Between => p1.push((id.clone(), *type_id)),
+                                Segment::After => p2.push((id.clone(), *type_id)),
                             },
                         }
                     }
-                    p0.iter().filter(|&i| !p1.contains(i)).for_each(|i| {
-                        config.templates.push(Template {
-                            content:  i.clone(),
-                            position: InsertionPosition::SequenceStart,
-                        });
-                    });
-                    p0.iter().filter(|&i| p1.contains(i)).for_each(|i| {
-                        config.templates.push(Template {
-                            content:  i.clone(),
-                            position: InsertionPosition::SubSequenceStart,
-                        });
-                    });
-                    p1.iter().filter(|&i| !p0.contains(i) && !p2.contains(i)).for_each(|i| {
-                        config.templates.push(Template {
-                            content:  i.clone(),
-                            position: InsertionPosition::SequenceContinuation,
-                        });
-                    });
-                    p1.iter().filter(|&i| p2.contains(i)).for_each(|i| {
-                        config.templates.push(Template {
-                            content:  i.clone(),
-                            position: InsertionPosition::SubSequenceEnd,
-                        });
-                    });
-                    p2.iter().filter(|&i| !p1.contains(i)).for_each(|i| {
-                        config.templates.push(Template {
-                            content:  i.clone(),
-                            position: InsertionPosition::SequenceEnd,
-                        });
-                    });
+                    // Tokens ending every sequence are the common prefix of the tokens after the first and second sequence,
+                    // tokens starting every sequence are the common suffix of the tokens before the first and second sequence.
+                    let same = |(a, b): &(&(String, u32), &(String, u32))| a.0 == b.0;
+                    let sub_end_len = p1.iter().zip(p2.iter()).take_while(same).count();
+                    let (sub_end, p1) = p1.split_at(sub_end_len);
+                    let (sub_end_second, p2) = p2.split_at(sub_end_len);
+                    let sub_start_len =
+                        p0.iter().rev().zip(p1.iter().rev()).take_while(same).count();
+                    let (p0, sub_start) = p0.split_at(p0.len() - sub_start_len);
+                    let (p1, sub_start_second) = p1.split_at(p1.len() - sub_start_len);
+
+                    // Tokens at the same position share a type id, sub-sequence tokens share it with their sequence
+                    let default = config.type_ids;
+                    let first = sequence_types.first().copied().unwrap_or(default.first);
+                    let second = sequence_types.get(1).copied().unwrap_or(default.second);
+                    let shares = |pieces: &[(String, u32)], type_id: u32| {
+                        pieces.iter().all(|(_, other)| *other == type_id)
+                    };
+                    let type_id = |pieces: &[(String, u32)], default: u32| {
+                        let type_id = pieces.first().map_or(default, |(_, type_id)| *type_id);
+                        if !shares(pieces, type_id) {
+                            log::warn!(
+                                "TemplateProcessing pair template has different type ids at the same position, using the first"
+                            );
+                        }
+                        type_id
+                    };
+                    if !shares(sub_start, first)
+                        || !shares(sub_end, first)
+                        || !shares(sub_start_second, second)
+                        || !shares(sub_end_second, second)
+                    {
+                        log::warn!(
+                            "TemplateProcessing pair template has sub-sequence type ids different from their sequence, using the sequence type ids"
+                        );
+                    }
+                    config.type_ids = TemplateTypeIds {
+                        sequence_start: type_id(p0, default.sequence_start),
+                        first,
+                        sequence_continuation: type_id(p1, default.sequence_continuation),
+                        second,
+                        sequence_end: type_id(p2, default.sequence_end),
+                    };
+
+                    for (pieces, position) in [
+                        (p0, InsertionPosition::SequenceStart),
+                        (sub_start, InsertionPosition::SubSequenceStart),
+                        (p1, InsertionPosition::SequenceContinuation),
+                        (sub_end, InsertionPosition::SubSequenceEnd),
+                        (p2, InsertionPosition::SequenceEnd),
+                    ] {
+                        for (piece, _) in pieces {
+                            config.templates.push(Template {
+                                content: piece.clone(),
+                                position,
+                            });
+                        }
+                    }
                 }
                 if config.templates.is_empty() && !single.is_empty() {
                     let mut past_sequence_marker = false;
@@ -1336,8 +1397,38 @@ pub fn convert_tokenizers(data: impl AsRef<[u8]>) -> Result<Definition, Conversi
             };
             (model, specials)
         }
+        hf::Model::WordLevel(wordlevel) => {
+            let specials = get_specials(Some(&wordlevel.unk_token), None);
+
+            if specials.get(wordlevel.unk_token.as_bytes()).is_some() {
+                config.fallback.insert(0, Fallback::Unknown);
+            } else {
+                return Err(ConversionError::InvalidData(format!(
+                    "Unknown token {:?} not found in specials",
+                    wordlevel.unk_token
+                )));
+            }
+
+            let mut vocab = wordlevel
+                .vocab
+                .into_iter()
+                .map::<Token, _>(|(token, id)| (token.into_bytes(), id).into())
+                .filter(|t| specials.get(&t.bytes).is_none())
+                .collect::<Vocab>();
+            vocab.sort_by(|Token { bytes: a, id: ai }, Token { bytes: b, id: bi }| {
+                ai.cmp(bi).then_with(|| a.cmp(b))
+            });
+
+            let mut specials = specials.into_values().collect::<SpecialVocab>();
+            specials.sort();
+
+            let model = Model::WordLevel { vocab };
+            (model, specials)
+        }
+    };
+    let Some(vocab) = model.try_vocab_mut() else {
+        return Err(ConversionError::InvalidData("Model has no vocabulary".to_string()));
     };
-    let vocab = model.vocab_mut();
 
     if let Some(padding) = tokenizer.padding {
         use hf::{PaddingDirection, PaddingStrategy};
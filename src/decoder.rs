@@ -1,14 +1,16 @@
 //! Decoder for the tokenizer.
 
+use alloc::borrow::Cow;
 use alloc::format;
-use alloc::string::String;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use core::fmt::Debug;
 
 use hashbrown::HashMap;
 
 use crate::{
-    Configuration, InsertionPosition, SpecialToken, SpecialTokenKind, SpecialVocab, TokenId, Vocab,
+    Configuration, InsertionPosition, Kitoken, SpecialToken, SpecialTokenKind, SpecialTokenKinds,
+    SpecialVocab, TokenId, Vocab,
 };
 
 /// Errors encountered during decoding.
@@ -18,16 +20,140 @@ pub enum DecodeError {
     /// A token could not be decoded.
     #[error("invalid token {0}")]
     InvalidToken(TokenId),
+    /// The decoded stream output does not continue the previously returned text.
+    #[error("invalid stream prefix")]
+    InvalidPrefix,
+}
+
+/// Behavior for token ids not in the vocabulary or the special vocabulary during decoding.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum UnknownTokenBehavior {
+    /// Returns [`DecodeError::InvalidToken`].
+    #[default]
+    Error,
+    /// Skips the token.
+    Skip,
+    /// Replaces the token with the given text.
+    Replace(String),
+    /// Replaces the token with the replacement character `U+FFFD`.
+    ReplacementCharacter,
+}
+
+/// Options for decoding a sequence of tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DecodeOptions {
+    /// The behavior for token ids not in the vocabulary or the special vocabulary.
+    ///
+    /// Replacements are inserted before the decoding steps of the configuration are applied.
+    pub unknown: UnknownTokenBehavior,
+}
+impl DecodeOptions {
+    /// Creates options with the given behavior for unknown token ids.
+    #[inline(always)]
+    pub fn with_unknown(unknown: UnknownTokenBehavior) -> Self {
+        Self { unknown }
+    }
+
+    /// Handles a token id not in the vocabulary or the special vocabulary.
+    ///
+    /// Returns `true` if a replacement was pushed, or an error if unknown tokens are not allowed.
+    #[inline(never)]
+    fn push_unknown(&self, result: &mut Vec<u8>, token: TokenId) -> Result<bool, DecodeError> {
+        match &self.unknown {
+            UnknownTokenBehavior::Error => return Err(DecodeError::InvalidToken(token)),
+            UnknownTokenBehavior::Skip => return Ok(false),
+            UnknownTokenBehavior::Replace(replacement) => result.extend(replacement.as_bytes()),
+            UnknownTokenBehavior::ReplacementCharacter => {
+                result.extend(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]).as_bytes())
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Token decoded individually, created with [`Kitoken::decode_pieces`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    /// The token.
+    pub id:         TokenId,
+    /// The bytes of the token from the vocabulary or the special vocabulary.
+    pub bytes:      Vec<u8>,
+    /// The display form of the token, the bytes with the replacement steps of the decoding configuration applied,
+    /// for example with the sentencepiece `▁` mapped back to a space. Parts of multi-byte characters are replaced with `U+FFFD`.
+    pub text:       String,
+    /// `true` if the token is in the special vocabulary.
+    pub special:    bool,
+    /// `true` if the token starts a word.
+    pub word_start: bool,
+    /// The `(start, end)` byte range of the token in the decoded output.
+    ///
+    /// Special tokens not included in the output have an empty range. Characters inserted during decoding are not part of any range.
+    pub range:      (usize, usize),
 }
 
 pub(crate) type DecoderMap = HashMap<TokenId, Vec<u8>>;
 pub(crate) type SpecialDecoderMap = HashMap<TokenId, SpecialToken>;
 
+/// Vocabulary of models mapping tokens to ids without storing them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImplicitVocab {
+    /// Each byte has the id `byte + offset`.
+    Bytes { offset: TokenId },
+    /// Each character has the id of its codepoint.
+    Chars,
+}
+impl ImplicitVocab {
+    /// Returns the bytes of the token, or `None` if the id is not in the vocabulary.
+    #[inline(always)]
+    pub(crate) fn token_bytes(self, token: TokenId) -> Option<Vec<u8>> {
+        match self {
+            ImplicitVocab::Bytes { offset } => {
+                let byte = u8::try_from(token.checked_sub(offset)?).ok()?;
+                Some(Vec::from([byte]))
+            }
+            ImplicitVocab::Chars => {
+                let char = char::from_u32(token)?;
+                Some(char.encode_utf8(&mut [0; 4]).as_bytes().to_vec())
+            }
+        }
+    }
+
+    /// Returns `true` if the id is in the vocabulary.
+    #[inline(always)]
+    pub(crate) fn contains(self, token: TokenId) -> bool {
+        match self {
+            ImplicitVocab::Bytes { offset } => {
+                token.checked_sub(offset).is_some_and(|byte| byte <= u8::MAX as TokenId)
+            }
+            ImplicitVocab::Chars => char::from_u32(token).is_some(),
+        }
+    }
+
+    /// Returns the number of tokens in the vocabulary.
+    #[inline(always)]
+    pub(crate) fn len(self) -> usize {
+        match self {
+            ImplicitVocab::Bytes { .. } => 256,
+            ImplicitVocab::Chars => (char::MAX as usize + 1) - 0x800,
+        }
+    }
+
+    /// Returns the maximum number of bytes of a token.
+    #[inline(always)]
+    pub(crate) fn max_token_bytes(self) -> usize {
+        match self {
+            ImplicitVocab::Bytes { .. } => 1,
+            ImplicitVocab::Chars => 4,
+        }
+    }
+}
+
 /// Decoder for the tokenizer.
 #[derive(Clone)]
 pub(crate) struct Decoder {
     vocab:    DecoderMap,
     specials: SpecialDecoderMap,
+    implicit: Option<ImplicitVocab>,
 
     subword_prefix: Option<String>,
 
@@ -36,7 +162,7 @@ pub(crate) struct Decoder {
 impl Decoder {
     #[inline(always)]
     pub(crate) fn new(vocab: &Vocab, specials: &SpecialVocab, config: &Configuration) -> Self {
-        let max_token_bytes = vocab.iter().map(|k| k.len()).max().unwrap().max(1);
+        let max_token_bytes = vocab.iter().map(|k| k.len()).max().unwrap_or_default().max(1);
         let specials = specials.iter().map(|special| (special.id, special.clone())).collect();
         let vocab = vocab.iter().map(|token| token.into()).collect();
         let subword_prefix = config.templates.iter().find_map(|template| {
@@ -49,20 +175,78 @@ impl Decoder {
         Self {
             vocab,
             specials,
+            implicit: None,
             subword_prefix,
             max_token_bytes,
         }
     }
 
+    /// Creates a decoder for a model without a stored vocabulary.
+    #[inline(always)]
+    pub(crate) fn new_implicit(
+        implicit: ImplicitVocab, specials: &SpecialVocab, config: &Configuration,
+    ) -> Self {
+        let mut decoder = Self::new(&Vocab::new(), specials, config);
+        decoder.implicit = Some(implicit);
+        decoder.max_token_bytes = implicit.max_token_bytes();
+        decoder
+    }
+
+    /// Returns the bytes of the token from the vocabulary or the special vocabulary.
+    #[inline(always)]
+    pub(crate) fn token_bytes(&self, token: TokenId) -> Option<Cow<'_, [u8]>> {
+        self.vocab
+            .get(&token)
+            .map(|bytes| Cow::Borrowed(bytes.as_slice()))
+            .or_else(|| self.specials.get(&token).map(|special| Cow::Borrowed(special.as_slice())))
+            .or_else(|| self.implicit?.token_bytes(token).map(Cow::Owned))
+    }
+
+    /// Returns the number of distinct tokens in the vocabulary and the special vocabulary.
+    #[inline(always)]
+    pub(crate) fn len(&self) -> usize {
+        let implicit = |id: TokenId| self.implicit.is_some_and(|implicit| implicit.contains(id));
+        let specials = self.specials.keys().filter(|id| !self.vocab.contains_key(*id));
+        let specials = specials.filter(|id| !implicit(**id)).count();
+        self.vocab.len() + self.implicit.map_or(0, |implicit| implicit.len()) + specials
+    }
+
+    /// Inserts the token into the special vocabulary.
+    #[inline(always)]
+    pub(crate) fn insert_special(&mut self, special: SpecialToken) {
+        self.specials.insert(special.id, special);
+    }
+
+    /// Removes the token from the special vocabulary.
+    #[inline(always)]
+    pub(crate) fn remove_special(&mut self, token: TokenId) {
+        self.specials.remove(&token);
+    }
+
+    /// Returns `true` if the token is in the special vocabulary.
+    #[inline(always)]
+    pub(crate) fn is_special(&self, token: TokenId) -> bool {
+        self.specials.contains_key(&token)
+    }
+
     #[inline(never)]
     pub(crate) fn decode(
-        &self, tokens: &[TokenId], decode_specials: &[SpecialTokenKind],
+        &self, tokens: &[TokenId], decode_specials: &[SpecialTokenKind], options: &DecodeOptions,
     ) -> Result<Vec<u8>, DecodeError> {
         let extend = self.subword_prefix.as_deref().unwrap_or_default();
         let mut result = Vec::<u8>::with_capacity(
             tokens.len() * self.max_token_bytes + tokens.len() * extend.len(),
         );
-        if !extend.is_empty() {
+        if let Some(implicit) = self.implicit {
+            Self::decode_implicit(
+                &mut result,
+                tokens,
+                implicit,
+                &self.specials,
+                decode_specials,
+                options,
+            )?;
+        } else if !extend.is_empty() {
             Self::decode_with_prefix(
                 &mut result,
                 tokens,
@@ -70,9 +254,66 @@ impl Decoder {
                 &self.vocab,
                 &self.specials,
                 decode_specials,
+                options,
             )?;
         } else {
-            Self::decode_direct(&mut result, tokens, &self.vocab, &self.specials, decode_specials)?;
+            Self::decode_direct(
+                &mut result,
+                tokens,
+                &self.vocab,
+                &self.specials,
+                decode_specials,
+                options,
+            )?;
+        }
+        Ok(result)
+    }
+
+    /// Returns `true` if the token is decoded from the special vocabulary instead of the vocabulary.
+    #[inline(always)]
+    pub(crate) fn decodes_special(&self, token: TokenId) -> bool {
+        !self.vocab.contains_key(&token) && self.specials.contains_key(&token)
+    }
+
+    /// Returns the subword prefix separating words during decoding, or `None` if tokens are concatenated directly.
+    #[inline(always)]
+    pub(crate) fn subword_prefix(&self) -> Option<&str> {
+        if self.implicit.is_some() {
+            return None;
+        }
+        self.subword_prefix.as_deref().filter(|prefix| !prefix.is_empty())
+    }
+
+    /// Decodes the tokens like [`decode`](Self::decode) and pushes the `(start, end)` byte range of each token in the output into `ranges`.
+    #[inline(never)]
+    pub(crate) fn decode_ranges(
+        &self, tokens: &[TokenId], decode_specials: &[SpecialTokenKind],
+        ranges: &mut Vec<(usize, usize)>,
+    ) -> Result<Vec<u8>, DecodeError> {
+        let prefix = self.subword_prefix();
+        let mut result = Vec::<u8>::with_capacity(tokens.len() * self.max_token_bytes);
+        for &token in tokens {
+            let (bytes, special) = if let Some(bytes) = self.vocab.get(&token) {
+                (Cow::Borrowed(bytes.as_slice()), None)
+            } else if let Some(special) = self.specials.get(&token) {
+                (Cow::Borrowed(special.as_slice()), Some(special.kind))
+            } else if let Some(bytes) =
+                self.implicit.and_then(|implicit| implicit.token_bytes(token))
+            {
+                (Cow::Owned(bytes), None)
+            } else {
+                return Err(DecodeError::InvalidToken(token));
+            };
+            let word = prefix
+                .is_some_and(|prefix| special.is_some() || !bytes.starts_with(prefix.as_bytes()));
+            if word && !result.is_empty() {
+                result.push(b' ');
+            }
+            let start = result.len();
+            if special.is_none_or(|kind| decode_specials.contains(&kind)) {
+                result.extend_from_slice(&bytes);
+            }
+            ranges.push((start, result.len()));
         }
         Ok(result)
     }
@@ -89,7 +330,7 @@ impl Decoder {
     )]
     fn decode_direct(
         result: &mut Vec<u8>, tokens: &[TokenId], vocab: &DecoderMap, specials: &SpecialDecoderMap,
-        decode_specials: &[SpecialTokenKind],
+        decode_specials: &[SpecialTokenKind], options: &DecodeOptions,
     ) -> Result<(), DecodeError> {
         for token in tokens {
             let bytes = vocab.get(token);
@@ -100,7 +341,7 @@ impl Decoder {
                     result.extend(special);
                 }
             } else {
-                return Err(DecodeError::InvalidToken(*token));
+                options.push_unknown(result, *token)?;
             }
         }
         Ok(())
@@ -119,6 +360,7 @@ impl Decoder {
     fn decode_with_prefix(
         result: &mut Vec<u8>, tokens: &[TokenId], prefix: &str, vocab: &DecoderMap,
         specials: &SpecialDecoderMap, decode_specials: &[SpecialTokenKind],
+        options: &DecodeOptions,
     ) -> Result<(), DecodeError> {
         for token in tokens {
             let bytes = vocab.get(token);
@@ -135,7 +377,46 @@ impl Decoder {
                     result.extend(special);
                 }
             } else {
-                return Err(DecodeError::InvalidToken(*token));
+                let len = result.len();
+                if !result.is_empty() {
+                    result.push(b' ');
+                }
+                if !options.push_unknown(result, *token)? {
+                    result.truncate(len);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[inline(never)]
+    fn decode_implicit(
+        result: &mut Vec<u8>, tokens: &[TokenId], implicit: ImplicitVocab,
+        specials: &SpecialDecoderMap, decode_specials: &[SpecialTokenKind],
+        options: &DecodeOptions,
+    ) -> Result<(), DecodeError> {
+        for &token in tokens {
+            if let Some(special) = specials.get(&token) {
+                if decode_specials.contains(&special.kind) {
+                    result.extend(special);
+                }
+                continue;
+            }
+            match implicit {
+                ImplicitVocab::Bytes { offset } => {
+                    match token.checked_sub(offset).and_then(|byte| u8::try_from(byte).ok()) {
+                        Some(byte) => result.push(byte),
+                        None => {
+                            options.push_unknown(result, token)?;
+                        }
+                    }
+                }
+                ImplicitVocab::Chars => match char::from_u32(token) {
+                    Some(char) => result.extend(char.encode_utf8(&mut [0; 4]).as_bytes()),
+                    None => {
+                        options.push_unknown(result, token)?;
+                    }
+                },
             }
         }
         Ok(())
@@ -147,8 +428,88 @@ impl Debug for Decoder {
         f.debug_struct("Decoder")
             .field("vocab", &format!("DecoderMap({})", self.vocab.len()))
             .field("specials", &format!("SpecialDecoderMap({})", self.specials.len()))
+            .field("implicit", &self.implicit)
             .field("subword_prefix", &self.subword_prefix)
             .field("max_token_bytes", &self.max_token_bytes)
             .finish()
     }
 }
+
+/// Incremental decoder for decoding a sequence of tokens one token at a time.
+///
+/// Created with [`Kitoken::decode_stream`]. Each call to [`DecodeStream::step`] decodes a window of the most recent tokens
+/// and returns the text added by the new token, once it forms complete UTF-8 characters.
+/// Since the window is post-processed as a whole, decoding steps like [`Decoding::Strip`](crate::Decoding::Strip)
+/// and the subword prefix handling are applied the same way as when decoding the full sequence.
+#[derive(Debug, Clone)]
+pub struct DecodeStream<'a> {
+    tokenizer: &'a Kitoken,
+    specials:  Vec<SpecialTokenKind>,
+
+    tokens:       Vec<TokenId>,
+    prefix:       Vec<u8>,
+    prefix_index: usize,
+}
+impl<'a> DecodeStream<'a> {
+    #[inline(always)]
+    pub(crate) fn new(tokenizer: &'a Kitoken, decode_specials: impl SpecialTokenKinds) -> Self {
+        let specials = decode_specials.as_kinds(tokenizer.meta()).to_vec();
+        Self {
+            tokenizer,
+            specials,
+            tokens: Vec::new(),
+            prefix: Vec::new(),
+            prefix_index: 0,
+        }
+    }
+
+    /// Decodes the next token of the sequence.
+    ///
+    /// Returns the newly decoded text, or `None` if the token does not complete any text yet,
+    /// for example when it contains only a part of a multi-byte character.
+    /// Returns an error if no byte sequence for the token exists in the decoder,
+    /// or if the post-processed output does not continue the previously returned text.
+    #[inline(never)]
+    pub fn step(&mut self, token: TokenId) -> Result<Option<String>, DecodeError> {
+        if self.prefix.is_empty() && !self.tokens.is_empty() {
+            let prefix = self.decode_window()?;
+            if core::str::from_utf8(&prefix).is_ok() {
+                self.prefix = prefix;
+                self.prefix_index = self.tokens.len();
+            }
+        }
+        self.tokens.push(token);
+        let decoded = self.decode_window()?;
+        if decoded.len() <= self.prefix.len() {
+            return Ok(None);
+        }
+        if !decoded.starts_with(&self.prefix) {
+            return Err(DecodeError::InvalidPrefix);
+        }
+        let text = match core::str::from_utf8(&decoded[self.prefix.len()..]) {
+            Ok(text) => text.to_string(),
+            Err(e) if e.error_len().is_none() => return Ok(None),
+            Err(_) => String::from_utf8_lossy(&decoded[self.prefix.len()..]).into_owned(),
+        };
+        let prefix_index = self.tokens.len() - self.prefix_index;
+        self.tokens.drain(..self.prefix_index);
+        self.prefix = self.decode_window()?;
+        self.prefix_index = prefix_index;
+        Ok(Some(text))
+    }
+
+    /// Returns the tokens in the current decoding window.
+    #[inline(always)]
+    pub fn tokens(&self) -> &[TokenId] {
+        &self.tokens
+    }
+
+    /// Decodes and post-processes the tokens in the current window.
+    #[inline(always)]
+    fn decode_window(&self) -> Result<Vec<u8>, DecodeError> {
+        let options = DecodeOptions::default();
+        let mut result = self.tokenizer.decoder.decode(&self.tokens, &self.specials, &options)?;
+        self.tokenizer.config.decode(&mut result);
+        Ok(result)
+    }
+}
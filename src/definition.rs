@@ -4,14 +4,34 @@
 
 use core::fmt::Debug;
 
+use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
+use hashbrown::{HashMap, HashSet};
 #[cfg(feature = "serialization")]
 use serde::{Deserialize, Serialize};
 
-use crate::{Configuration, InitializationError, Kitoken, Scores, SpecialVocab, Vocab};
+use crate::{
+    Configuration, ImplicitVocab, InitializationError, InsertionPosition, Kitoken, Processing,
+    Scores, SpecialVocab, Token, TokenBytes, TokenId, Vocab,
+};
+
+/// Errors encountered when modifying a definition.
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum DefinitionError {
+    /// The operation is not supported for the model.
+    #[error("operation not supported for the model")]
+    UnsupportedModel,
+    /// The token already exists in the vocabulary or the special vocabulary.
+    #[error("duplicate token {0:?}")]
+    DuplicateToken(TokenBytes),
+    /// The token can not be built by merging existing tokens.
+    #[error("unreachable token {0:?}")]
+    UnreachableToken(TokenBytes),
+}
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "serialization", derive(Deserialize, Serialize))]
@@ -61,25 +81,72 @@ pub enum Model {
         /// The maximum number of characters in a piece.
         max_word_chars: u32,
     },
+    WordLevel {
+        /// The encoder vocabulary without special tokens.
+        /// Sorted by token id.
+        vocab: Vocab,
+    },
+    /// Model without a stored vocabulary encoding each byte into the token with the id `byte + offset`.
+    /// Special tokens must not use these ids.
+    Bytes {
+        /// The id of the token of the byte `0`.
+        offset: TokenId,
+    },
+    /// Model without a stored vocabulary encoding each character into the token with the id of its codepoint.
+    /// Special tokens must use ids that are not valid codepoints, like ids above `0x10FFFF`.
+    Chars,
 }
 impl Model {
     /// Returns the encoder vocabulary.
+    ///
+    /// [`Model::Bytes`] and [`Model::Chars`] have no stored vocabulary and return an empty vocabulary.
+    /// Use [`vocab_size`](Self::vocab_size) for the number of tokens of all models.
     #[inline(always)]
     pub fn vocab(&self) -> &Vocab {
+        static EMPTY: Vocab = Vocab::new();
         match self {
             Model::BytePair { vocab, .. } => vocab,
             Model::Unigram { vocab, .. } => vocab,
             Model::WordPiece { vocab, .. } => vocab,
+            Model::WordLevel { vocab } => vocab,
+            Model::Bytes { .. } | Model::Chars => &EMPTY,
         }
     }
 
     /// Returns the encoder vocabulary as mutable.
+    ///
+    /// # Panics
+    ///
+    /// Panics for [`Model::Bytes`] and [`Model::Chars`], which have no stored vocabulary.
+    /// Use [`try_vocab_mut`](Self::try_vocab_mut) for models that may have no stored vocabulary.
     #[inline(always)]
     pub fn vocab_mut(&mut self) -> &mut Vocab {
+        self.try_vocab_mut().expect("model has no stored vocabulary")
+    }
+
+    /// Returns the encoder vocabulary as mutable.
+    ///
+    /// Returns `None` for models without a stored vocabulary.
+    #[inline(always)]
+    pub fn try_vocab_mut(&mut self) -> Option<&mut Vocab> {
         match self {
-            Model::BytePair { vocab, .. } => vocab,
-            Model::Unigram { vocab, .. } => vocab,
-            Model::WordPiece { vocab, .. } => vocab,
+            Model::BytePair { vocab, .. } => Some(vocab),
+            Model::Unigram { vocab, .. } => Some(vocab),
+            Model::WordPiece { vocab, .. } => Some(vocab),
+            Model::WordLevel { vocab } => Some(vocab),
+            Model::Bytes { .. } | Model::Chars => None,
+        }
+    }
+
+    /// Returns the number of tokens in the encoder vocabulary.
+    ///
+    /// Includes the implicit tokens of models without a stored vocabulary.
+    #[inline(always)]
+    pub fn vocab_size(&self) -> usize {
+        match self {
+            Model::Bytes { offset } => ImplicitVocab::Bytes { offset: *offset }.len(),
+            Model::Chars => ImplicitVocab::Chars.len(),
+            model => model.vocab().len(),
         }
     }
 }
@@ -105,6 +172,14 @@ impl Debug for Model {
                 .field("vocab", &format!("Vocab({})", vocab.len()))
                 .field("max_word_chars", max_word_chars)
                 .finish(),
+            Model::WordLevel { vocab } => f
+                .debug_struct("Model::WordLevel")
+                .field("vocab", &format!("Vocab({})", vocab.len()))
+                .finish(),
+            Model::Bytes { offset } => {
+                f.debug_struct("Model::Bytes").field("offset", offset).finish()
+            }
+            Model::Chars => f.debug_struct("Model::Chars").finish(),
         }
     }
 }
@@ -125,6 +200,221 @@ pub struct Definition {
     /// The tokenizer configuration.
     pub config:   Configuration,
 }
+impl Definition {
+    /// Returns the next free token id after the vocabulary and the special vocabulary.
+    #[inline(never)]
+    pub fn next_token_id(&self) -> TokenId {
+        let vocab = self.model.vocab().iter().map(|token| token.id);
+        let implicit = match self.model {
+            Model::Bytes { offset } => Some(offset + u8::MAX as TokenId),
+            Model::Chars => Some(char::MAX as TokenId),
+            _ => None,
+        };
+        let specials = self.specials.iter().map(|special| special.id);
+        vocab.chain(implicit).chain(specials).max().map_or(0, |id| id + 1)
+    }
+
+    /// Appends the given tokens to the vocabulary of a [`Model::BytePair`] model.
+    ///
+    /// The tokens are ranked after the existing tokens in the given order, and get the next free ids after the vocabulary and the special vocabulary.
+    /// Every token must be reachable, meaning merging its bytes, or characters for character-based models, by rank like during encoding
+    /// builds the token, using the tokens of the vocabulary, the tokens added before it and the token itself.
+    /// Tokens of a single byte, or a single character for character-based models, are always reachable.
+    ///
+    /// Returns the ids of the added tokens, or an error if the model is not a byte pair model, or if a token is a duplicate or unreachable.
+    /// The definition is left unchanged on error.
+    #[inline(never)]
+    pub fn extend_vocab(
+        &mut self, tokens: impl IntoIterator<Item = impl Into<TokenBytes>>,
+    ) -> Result<Vec<TokenId>, DefinitionError> {
+        let added = self.reachable_tokens(tokens)?;
+        let ids = added.iter().map(|token| token.id).collect();
+        if let Model::BytePair { vocab, .. } = &mut self.model {
+            vocab.extend(added);
+        }
+        Ok(ids)
+    }
+
+    /// Checks that the given tokens are reachable in a [`Model::BytePair`] model and assigns them the next free ids.
+    #[inline(never)]
+    fn reachable_tokens(
+        &self, tokens: impl IntoIterator<Item = impl Into<TokenBytes>>,
+    ) -> Result<Vec<Token>, DefinitionError> {
+        let Model::BytePair { vocab, chars } = &self.model else {
+            return Err(DefinitionError::UnsupportedModel);
+        };
+        let end_of_word = self.end_of_word();
+        let specials = self.specials.iter().map(|special| special.bytes.as_slice());
+        let specials = specials.collect::<HashSet<_>>();
+        let mut ranks = vocab
+            .iter()
+            .enumerate()
+            .map(|(i, token)| (token.bytes.clone(), i))
+            .collect::<HashMap<_, _>>();
+        let mut added = Vec::<Token>::new();
+        for (id, bytes) in (self.next_token_id()..).zip(tokens) {
+            let bytes = bytes.into();
+            if ranks.contains_key(&bytes) || specials.contains(bytes.as_slice()) {
+                return Err(DefinitionError::DuplicateToken(bytes));
+            }
+            if bytes.is_empty() || (*chars && core::str::from_utf8(&bytes).is_err()) {
+                return Err(DefinitionError::UnreachableToken(bytes));
+            }
+            ranks.insert(bytes.clone(), ranks.len());
+            let rank = |part: &[u8]| ranks.get(part).copied();
+            if Self::merge_units(&bytes, *chars, end_of_word, rank, |_| {}) > 1 {
+                return Err(DefinitionError::UnreachableToken(bytes));
+            }
+            added.push((id, bytes).into());
+        }
+        Ok(added)
+    }
+
+    /// Returns the suffix of the [`InsertionPosition::WordEnd`] template, if any.
+    #[inline(never)]
+    fn end_of_word(&self) -> Option<&[u8]> {
+        self.config.templates.iter().find_map(|template| {
+            if template.position == InsertionPosition::WordEnd {
+                Some(template.content.as_bytes())
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Removes the tokens for which `keep` returns `false` from the vocabulary and assigns contiguous ids to the remaining tokens.
+    ///
+    /// Tokens required to keep the remaining tokens reachable are kept regardless of `keep`. For [`Model::BytePair`] models,
+    /// this includes every token merged while building a remaining token. Special tokens and tokens referenced by the
+    /// post-tokenization processing are always kept. The remaining tokens keep their order, and the ids of the special tokens
+    /// and the processing are updated.
+    ///
+    /// Models without a stored vocabulary are left unchanged.
+    ///
+    /// Returns a map from the old ids to the new ids of the remaining tokens, ordered by old id.
+    #[inline(never)]
+    pub fn prune(&mut self, keep: impl Fn(&Token) -> bool) -> BTreeMap<TokenId, TokenId> {
+        if matches!(self.model, Model::Bytes { .. } | Model::Chars) {
+            return self.specials.iter().map(|special| (special.id, special.id)).collect();
+        }
+        let vocab = self.model.vocab();
+        let processing_ids =
+            self.config.processing.iter().filter_map(|processing| match processing {
+                Processing::Strip { id, .. }
+                | Processing::Collapse { id }
+                | Processing::Pad { id, .. } => Some(*id),
+                _ => None,
+            });
+        let processing_ids = processing_ids.collect::<Vec<_>>();
+        let mut kept = vocab
+            .iter()
+            .map(|token| keep(token) || processing_ids.contains(&token.id))
+            .collect::<Vec<_>>();
+        if let Model::BytePair { vocab, chars } = &self.model {
+            let end_of_word = self.end_of_word();
+            let ranks = vocab
+                .iter()
+                .enumerate()
+                .map(|(i, token)| (token.bytes.as_slice(), i))
+                .collect::<HashMap<_, _>>();
+            let rank = |part: &[u8]| ranks.get(part).copied();
+            for (i, token) in vocab.iter().enumerate() {
+                if kept[i] {
+                    Self::merge_units(&token.bytes, *chars, end_of_word, rank, |rank| {
+                        kept[rank] = true
+                    });
+                }
+            }
+        }
+
+        let mut ids = vocab
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| **kept)
+            .map(|(token, _)| token.id)
+            .chain(self.specials.iter().map(|special| special.id))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        let ids = ids.into_iter().zip(0..).collect::<BTreeMap<TokenId, TokenId>>();
+
+        let retain = |vocab: &mut Vocab| {
+            let mut kept = kept.iter();
+            vocab.retain(|_| *kept.next().unwrap());
+            for token in vocab.iter_mut() {
+                token.id = ids[&token.id];
+            }
+        };
+        match &mut self.model {
+            Model::Unigram { vocab, scores } => {
+                let mut kept = kept.iter();
+                scores.retain(|_| *kept.next().unwrap());
+                retain(vocab);
+            }
+            model => {
+                if let Some(vocab) = model.try_vocab_mut() {
+                    retain(vocab)
+                }
+            }
+        }
+        for special in self.specials.iter_mut() {
+            special.id = ids[&special.id];
+        }
+        for processing in self.config.processing.iter_mut() {
+            match processing {
+                Processing::Strip { id, .. }
+                | Processing::Collapse { id }
+                | Processing::Pad { id, .. } => {
+                    *id = ids.get(id).copied().unwrap_or(*id);
+                }
+                _ => {}
+            }
+        }
+        ids
+    }
+
+    /// Merges the characters or bytes of the given token in a byte pair vocabulary by rank, like during encoding.
+    ///
+    /// The end-of-word suffix is merged into the last character or byte, like during encoding.
+    /// Calls `used` with the rank of every token of the initial parts and of every merged token.
+    ///
+    /// Returns the number of remaining parts, `1` if the token is built by the merges.
+    #[inline(never)]
+    fn merge_units(
+        bytes: &[u8], chars: bool, end_of_word: Option<&[u8]>,
+        rank: impl Fn(&[u8]) -> Option<usize>, mut used: impl FnMut(usize),
+    ) -> usize {
+        let units = match end_of_word {
+            Some(end_of_word) if bytes.len() > end_of_word.len() => {
+                bytes.strip_suffix(end_of_word).unwrap_or(bytes)
+            }
+            _ => bytes,
+        };
+        let mut parts = match core::str::from_utf8(units) {
+            Ok(text) if chars => text.char_indices().map(|(i, _)| i).collect::<Vec<_>>(),
+            _ => (0..units.len()).collect::<Vec<_>>(),
+        };
+        parts.push(bytes.len());
+        for part in parts.windows(2) {
+            if let Some(rank) = rank(&bytes[part[0]..part[1]]) {
+                used(rank);
+            }
+        }
+        while parts.len() > 2 {
+            let merge = parts
+                .windows(3)
+                .enumerate()
+                .filter_map(|(i, part)| rank(&bytes[part[0]..part[2]]).map(|rank| (rank, i)))
+                .min();
+            let Some((rank, i)) = merge else {
+                break;
+            };
+            used(rank);
+            parts.remove(i + 1);
+        }
+        parts.len() - 1
+    }
+}
 impl TryFrom<Definition> for Kitoken {
     type Error = InitializationError;
 
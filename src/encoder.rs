@@ -1,19 +1,27 @@
 //! Encoder for the tokenizer.
 
 use alloc::borrow::Cow;
+use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::any::Any;
 use core::borrow::Borrow;
 use core::fmt::Debug;
 use core::ops::Deref;
 
-use crate::{Model, TokenId};
+use crate::{Dropout, Encoding, Model, Rng, Segmentation, TokenId};
 
 mod bytepair;
+mod bytes;
+mod chars;
 mod unigram;
+mod wordlevel;
 mod wordpiece;
 
 pub(crate) use bytepair::*;
+pub(crate) use bytes::*;
+pub(crate) use chars::*;
 pub(crate) use unigram::*;
+pub(crate) use wordlevel::*;
 pub(crate) use wordpiece::*;
 
 /// Errors encountered during encoding.
@@ -23,6 +31,15 @@ pub enum EncodeError {
     /// A piece could not be encoded.
     #[error("invalid piece {0:?}")]
     InvalidPiece(Vec<u8>),
+    /// The operation is not supported by the model.
+    #[error("operation not supported by the model")]
+    UnsupportedModel,
+    /// A token is not in the vocabulary or the special vocabulary.
+    #[error("invalid token {0}")]
+    InvalidToken(TokenId),
+    /// The configuration has no padding step and the special vocabulary has no padding token.
+    #[error("no padding token")]
+    MissingPadToken,
 }
 
 /// Part of a text.
@@ -30,6 +47,8 @@ pub enum EncodeError {
 pub(crate) struct TextPart<'a> {
     pub text:    Cow<'a, str>,
     pub special: TokenId,
+    /// Byte offset of the part in the normalized input.
+    pub start:   usize,
 }
 impl Borrow<[u8]> for TextPart<'_> {
     #[inline(always)]
@@ -46,6 +65,129 @@ impl Deref for TextPart<'_> {
     }
 }
 
+/// Output of the encoder.
+///
+/// Implemented for plain token sequences, for sequences with offsets, and for token counts.
+pub(crate) trait EncodeOutput {
+    /// Appends a token covering `start..end` of the normalized input.
+    fn push_token(&mut self, token: TokenId, start: usize, end: usize);
+
+    /// Returns the number of tokens in the output.
+    fn token_count(&self) -> usize;
+
+    /// Truncates the output to `len` tokens.
+    fn truncate_tokens(&mut self, len: usize);
+
+    /// Reverses the order of the tokens starting at `start`.
+    fn reverse_tokens(&mut self, start: usize);
+
+    /// Limits the offsets of the tokens starting at `start` to end at or before `limit`.
+    fn limit_offsets(&mut self, start: usize, limit: usize);
+}
+impl EncodeOutput for Vec<TokenId> {
+    #[inline(always)]
+    fn push_token(&mut self, token: TokenId, _start: usize, _end: usize) {
+        self.push(token);
+    }
+
+    #[inline(always)]
+    fn token_count(&self) -> usize {
+        self.len()
+    }
+
+    #[inline(always)]
+    fn truncate_tokens(&mut self, len: usize) {
+        self.truncate(len);
+    }
+
+    #[inline(always)]
+    fn reverse_tokens(&mut self, start: usize) {
+        self[start..].reverse();
+    }
+
+    #[inline(always)]
+    fn limit_offsets(&mut self, _start: usize, _limit: usize) {}
+}
+impl EncodeOutput for usize {
+    #[inline(always)]
+    fn push_token(&mut self, _token: TokenId, _start: usize, _end: usize) {
+        *self += 1;
+    }
+
+    #[inline(always)]
+    fn token_count(&self) -> usize {
+        *self
+    }
+
+    #[inline(always)]
+    fn truncate_tokens(&mut self, len: usize) {
+        *self = (*self).min(len);
+    }
+
+    #[inline(always)]
+    fn reverse_tokens(&mut self, _start: usize) {}
+
+    #[inline(always)]
+    fn limit_offsets(&mut self, _start: usize, _limit: usize) {}
+}
+impl EncodeOutput for Encoding {
+    #[inline(always)]
+    fn push_token(&mut self, token: TokenId, start: usize, end: usize) {
+        self.tokens.push(token);
+        self.offsets.push((start, end));
+        self.type_ids.push(0);
+    }
+
+    #[inline(always)]
+    fn token_count(&self) -> usize {
+        self.tokens.len()
+    }
+
+    #[inline(always)]
+    fn truncate_tokens(&mut self, len: usize) {
+        self.tokens.truncate(len);
+        self.offsets.truncate(len);
+        self.type_ids.truncate(len);
+    }
+
+    #[inline(always)]
+    fn reverse_tokens(&mut self, start: usize) {
+        self.tokens[start..].reverse();
+        self.offsets[start..].reverse();
+        self.type_ids[start..].reverse();
+    }
+
+    #[inline(always)]
+    fn limit_offsets(&mut self, start: usize, limit: usize) {
+        for (offset_start, offset_end) in &mut self.offsets[start..] {
+            *offset_start = (*offset_start).min(limit);
+            *offset_end = (*offset_end).min(limit);
+        }
+    }
+}
+
+/// Scratch buffers of an encoder, reused across calls.
+///
+/// Holds the buffers of a single encoder type, which are replaced when used with a different encoder.
+#[derive(Default)]
+pub(crate) struct EncodeBuffers(Option<Box<dyn Any + Send + Sync>>);
+impl EncodeBuffers {
+    /// Returns the buffers of type `T`, creating them if they don't exist.
+    #[inline(always)]
+    pub fn get_or_default<T: Any + Send + Sync + Default>(&mut self) -> &mut T {
+        if !self.0.as_ref().is_some_and(|buffers| buffers.is::<T>()) {
+            self.0 = Some(Box::new(T::default()));
+        }
+        self.0.as_mut().and_then(|buffers| buffers.downcast_mut()).unwrap()
+    }
+}
+impl Debug for EncodeBuffers {
+    #[inline(never)]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_tuple("EncodeBuffers").finish_non_exhaustive()
+    }
+}
+
 /// Encoder for the tokenizer.
 pub(crate) trait Encoder: Debug + Send + Sync + 'static {
     /// Encodes the given parts into a sequence of tokens.
@@ -55,6 +197,73 @@ pub(crate) trait Encoder: Debug + Send + Sync + 'static {
     /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
     fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError>;
 
+    /// Encodes the given parts into a sequence of tokens with the byte range of each token in the normalized input.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError>;
+
+    /// Counts the tokens the given parts are encoded into without allocating the list of tokens.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn count(&self, text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError>;
+
+    /// Encodes the given parts and appends the tokens to `result`, reusing the scratch buffers.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError>;
+
+    /// Returns the id of the token with the given bytes in the vocabulary.
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId>;
+
     /// Returns the vocabulary and scores.
     fn model(&self) -> Model;
+
+    /// Returns the encoder as a [`DropoutEncoder`], or `None` if the encoder does not use merges.
+    #[inline(always)]
+    fn as_dropout(&self) -> Option<&dyn DropoutEncoder> {
+        None
+    }
+
+    /// Returns the encoder as a [`ScoredEncoder`], or `None` if the encoder does not score segmentations.
+    #[inline(always)]
+    fn as_scored(&self) -> Option<&dyn ScoredEncoder> {
+        None
+    }
+}
+
+/// Encoder using merges that can be skipped randomly.
+pub(crate) trait DropoutEncoder: Encoder {
+    /// Encodes the given parts into a sequence of tokens, randomly skipping merges with the dropout probability.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn encode_dropout(
+        &self, text: &str, parts: &mut [TextPart], dropout: Dropout,
+    ) -> Result<Vec<TokenId>, EncodeError>;
+}
+
+/// Encoder scoring segmentations with the log-probabilities of their tokens.
+pub(crate) trait ScoredEncoder: Encoder {
+    /// Encodes the given parts into the `n` best segmentations with their log-probabilities, ordered from best to worst.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn encode_nbest(
+        &self, parts: &mut [TextPart], n: usize,
+    ) -> Result<Vec<Segmentation>, EncodeError>;
+
+    /// Samples a segmentation of the given parts with its log-probability.
+    ///
+    /// Samples from all segmentations if `nbest` is `0`, and from the `nbest` best segmentations otherwise.
+    /// The probability of each segmentation is proportional to its probability raised to the power of `alpha`.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    fn encode_sample(
+        &self, parts: &mut [TextPart], alpha: f32, nbest: usize, rng: &mut Rng,
+    ) -> Result<Segmentation, EncodeError>;
+
+    /// Returns the log-probability of the token with the given id and bytes.
+    ///
+    /// Returns `None` if the token is not in the vocabulary and not the unknown token.
+    fn token_score(&self, id: TokenId, bytes: &[u8]) -> Option<f64>;
 }
@@ -11,8 +11,9 @@ use hashbrown::HashMap;
 use orx_priority_queue::{DaryHeapOfIndices, PriorityQueue, PriorityQueueDecKey};
 
 use crate::{
-    Configuration, EncodeError, Encoder, Fallback, InitializationError, InsertionPosition, Model,
-    SpecialToken, SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, Vocab,
+    Configuration, Dropout, DropoutEncoder, EncodeBuffers, EncodeError, EncodeOutput, Encoder,
+    Encoding, Fallback, InitializationError, InsertionPosition, Model, SpecialToken,
+    SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, Vocab,
 };
 
 type TokenRank = u32;
@@ -57,6 +58,25 @@ type VocabMap = HashMap<TokenBytes, TokenId>;
 type RankMap = HashMap<TokenBytes, TokenRank>;
 type PieceHeap = DaryHeapOfIndices<u32, LinkedPart, 4>;
 
+/// Scratch buffers of the BytePair encoder.
+struct BytePairBuffers {
+    buffer:  Vec<RankedPart>,
+    indices: Vec<(u32, u32)>,
+    heap:    PieceHeap,
+    dropout: Option<Dropout>,
+}
+impl Default for BytePairBuffers {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            buffer:  Vec::with_capacity(BytePair::ENCODE_BUFFER_SIZE),
+            indices: Vec::new(),
+            heap:    PieceHeap::with_index_bound(BytePair::ENCODE_BUFFER_SIZE),
+            dropout: None,
+        }
+    }
+}
+
 /// BytePair and CharPair encoder.
 #[derive(Clone)]
 pub(crate) struct BytePair {
@@ -89,23 +109,39 @@ impl Debug for BytePair {
 impl Encoder for BytePair {
     #[inline(always)]
     fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError> {
-        if let Some(end_of_word) = &self.end_of_word {
-            for part in parts.iter_mut() {
-                if part.special == Token::INVALID {
-                    part.text.to_mut().push_str(end_of_word);
-                }
-            }
-        }
         let mut result =
             Vec::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
-        if self.chars {
-            self.encode_chars(parts, &self.fallback, &mut result)?;
-        } else {
-            self.encode_bytes(parts, &self.fallback, &mut result)?;
-        }
+        self.encode_parts(parts, &mut result, &mut BytePairBuffers::default())?;
         Ok(result)
     }
 
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result =
+            Encoding::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        self.encode_parts(parts, &mut result, &mut BytePairBuffers::default())?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        let mut result = 0;
+        self.encode_parts(parts, &mut result, &mut BytePairBuffers::default())?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        self.encode_parts(parts, result, buffers.get_or_default())
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        self.vocab.get(bytes).copied()
+    }
+
     #[inline(always)]
     fn model(&self) -> Model {
         let mut vocab = self.vocab.iter().map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>();
@@ -121,6 +157,26 @@ impl Encoder for BytePair {
         let chars = self.chars;
         Model::BytePair { vocab, chars }
     }
+
+    #[inline(always)]
+    fn as_dropout(&self) -> Option<&dyn DropoutEncoder> {
+        Some(self)
+    }
+}
+impl DropoutEncoder for BytePair {
+    #[inline(always)]
+    fn encode_dropout(
+        &self, text: &str, parts: &mut [TextPart], dropout: Dropout,
+    ) -> Result<Vec<TokenId>, EncodeError> {
+        let mut result =
+            Vec::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        let mut buffers = BytePairBuffers {
+            dropout: Some(dropout),
+            ..Default::default()
+        };
+        self.encode_parts(parts, &mut result, &mut buffers)?;
+        Ok(result)
+    }
 }
 impl BytePair {
     const ENCODE_BUFFER_SIZE: usize = 256;
@@ -170,44 +226,77 @@ impl BytePair {
     }
 }
 impl BytePair {
+    /// Encodes the given parts into the output, appending the end-of-word suffix to non-special parts.
+    #[inline(always)]
+    fn encode_parts(
+        &self, parts: &mut [TextPart], result: &mut impl EncodeOutput,
+        buffers: &mut BytePairBuffers,
+    ) -> Result<(), EncodeError> {
+        if let Some(end_of_word) = &self.end_of_word {
+            for part in parts.iter_mut() {
+                if part.special == Token::INVALID {
+                    part.text.to_mut().push_str(end_of_word);
+                }
+            }
+        }
+        buffers.buffer.clear();
+        if self.chars {
+            self.encode_chars(parts, &self.fallback, result, buffers)
+        } else {
+            self.encode_bytes(parts, &self.fallback, result, buffers)
+        }
+    }
+
     /// Encodes the given parts into a sequence of tokens starting at individual bytes.
     #[inline(never)]
     fn encode_bytes(
-        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut Vec<TokenId>,
+        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut impl EncodeOutput,
+        buffers: &mut BytePairBuffers,
     ) -> Result<(), EncodeError> {
-        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
-        let mut heap = PieceHeap::with_index_bound(Self::ENCODE_BUFFER_SIZE);
+        let BytePairBuffers {
+            buffer,
+            heap,
+            dropout,
+            ..
+        } = buffers;
         let end_of_word_len = self.end_of_word.as_ref().map(|e| e.len()).unwrap_or(0);
         for part in parts {
             if part.special != Token::INVALID {
-                result.push(part.special);
+                result.push_token(part.special, part.start, part.start + part.len());
                 continue;
             }
-            if part.len() <= self.max_token_bytes
+            let first = result.token_count();
+            if dropout.is_none()
+                && part.len() <= self.max_token_bytes
                 && part.len() >= self.min_token_bytes
                 && let Some(&token) = self.vocab.get(part.as_bytes())
             {
-                result.push(token);
-                continue;
-            }
-            if part.len() > Self::ENCODE_LINEAR_LIMIT {
+                result.push_token(token, part.start, part.start + part.len());
+            } else if part.len() > Self::ENCODE_LINEAR_LIMIT {
                 self.encode_pairs_heap(
                     part.as_bytes(),
-                    &mut buffer,
-                    &mut heap,
+                    buffer,
+                    heap,
                     result,
                     (0..(part.len() - end_of_word_len)).map(|i| i as u32).map(|i| (i, 1)),
                     fallback,
+                    part.start,
+                    dropout.as_mut(),
                 )?;
             } else {
                 self.encode_pairs(
                     part.as_bytes(),
-                    &mut buffer,
+                    buffer,
                     result,
                     (0..(part.len() - end_of_word_len)).map(|i| i as u32),
                     fallback,
+                    part.start,
+                    dropout.as_mut(),
                 )?;
             }
+            if end_of_word_len > 0 {
+                result.limit_offsets(first, part.start + part.len() - end_of_word_len);
+            }
             buffer.clear();
         }
         Ok(())
@@ -216,46 +305,62 @@ impl BytePair {
     /// Encodes the given parts into a sequence of tokens starting at individual characters.
     #[inline(never)]
     fn encode_chars(
-        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut Vec<TokenId>,
+        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut impl EncodeOutput,
+        buffers: &mut BytePairBuffers,
     ) -> Result<(), EncodeError> {
-        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
-        let mut indices = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
-        let mut heap = PieceHeap::with_index_bound(Self::ENCODE_BUFFER_SIZE);
+        let BytePairBuffers {
+            buffer,
+            indices,
+            heap,
+            dropout,
+        } = buffers;
+        // the char indices are only used here, allocate them on first use
+        indices.clear();
+        indices.reserve(Self::ENCODE_BUFFER_SIZE);
         let end_of_word_len = self.end_of_word.as_ref().map(|e| e.len()).unwrap_or(0);
         for part in parts {
             if part.special != Token::INVALID {
-                result.push(part.special);
+                result.push_token(part.special, part.start, part.start + part.len());
                 continue;
             }
-            if part.len() <= self.max_token_bytes
+            let first = result.token_count();
+            if dropout.is_none()
+                && part.len() <= self.max_token_bytes
                 && part.len() >= self.min_token_bytes
                 && let Some(&token) = self.vocab.get(part.as_bytes())
             {
-                result.push(token);
-                continue;
-            }
-            indices.extend(
-                part[..part.len() - end_of_word_len]
-                    .char_indices()
-                    .map(|(s, _, c)| (s as u32, c.len_utf8() as u32)),
-            );
-            if indices.len() > Self::ENCODE_LINEAR_LIMIT {
-                self.encode_pairs_heap(
-                    part.as_bytes(),
-                    &mut buffer,
-                    &mut heap,
-                    result,
-                    indices.drain(..),
-                    fallback,
-                )?;
+                result.push_token(token, part.start, part.start + part.len());
             } else {
-                self.encode_pairs(
-                    part.as_bytes(),
-                    &mut buffer,
-                    result,
-                    indices.drain(..).map(|(i, _)| i),
-                    fallback,
-                )?;
+                indices.extend(
+                    part[..part.len() - end_of_word_len]
+                        .char_indices()
+                        .map(|(s, _, c)| (s as u32, c.len_utf8() as u32)),
+                );
+                if indices.len() > Self::ENCODE_LINEAR_LIMIT {
+                    self.encode_pairs_heap(
+                        part.as_bytes(),
+                        buffer,
+                        heap,
+                        result,
+                        indices.drain(..),
+                        fallback,
+                        part.start,
+                        dropout.as_mut(),
+                    )?;
+                } else {
+                    self.encode_pairs(
+                        part.as_bytes(),
+                        buffer,
+                        result,
+                        indices.drain(..).map(|(i, _)| i),
+                        fallback,
+                        part.start,
+                        dropout.as_mut(),
+                    )?;
+                }
+            }
+            if end_of_word_len > 0 {
+                result.limit_offsets(first, part.start + part.len() - end_of_word_len);
             }
         }
         Ok(())
@@ -264,11 +369,15 @@ impl BytePair {
 impl BytePair {
     /// Encodes the given piece into a sequence of tokens using the BPE algorithm.
     ///
+    /// `offset` is the byte offset of the piece in the normalized input.
+    ///
     /// Returns an error if no token for a part exists in the encoder, no unknown token id is set in the configuration, and no fallback is set.
     #[inline(never)]
+    #[allow(clippy::too_many_arguments)]
     fn encode_pairs(
-        &self, piece: &[u8], buffer: &mut Vec<RankedPart>, result: &mut Vec<TokenId>,
-        indices: impl Iterator<Item = u32>, fallback: &[Fallback],
+        &self, piece: &[u8], buffer: &mut Vec<RankedPart>, result: &mut impl EncodeOutput,
+        indices: impl Iterator<Item = u32>, fallback: &[Fallback], offset: usize,
+        mut dropout: Option<&mut Dropout>,
     ) -> Result<(), EncodeError> {
         let start = buffer.len();
         buffer.extend(indices.map(|i| RankedPart {
@@ -279,12 +388,18 @@ impl BytePair {
             start: piece.len() as _,
             rank:  TokenRank::MAX,
         });
-        BytePair::merge_bpe_parts(piece, buffer, start, &self.ranks);
+        if let Some(dropout) = dropout.as_deref_mut() {
+            BytePair::merge_bpe_parts_dropout(piece, buffer, start, &self.ranks, dropout);
+        } else {
+            BytePair::merge_bpe_parts(piece, buffer, start, &self.ranks);
+        }
         let end = buffer.len() - 1;
         for i in start..end {
+            let piece_start = offset + buffer[i].start as usize;
+            let piece_end = offset + buffer[i + 1].start as usize;
             let piece = &piece[buffer[i].start as usize..buffer[i + 1].start as usize];
             if let Some(&token) = self.vocab.get(piece) {
-                result.push(token);
+                result.push_token(token, piece_start, piece_end);
             } else if fallback.first() == Some(&Fallback::Bytes) {
                 let end = if let Some(end_of_word) = &self.end_of_word {
                     piece.len() - end_of_word.len()
@@ -297,9 +412,11 @@ impl BytePair {
                     result,
                     0..(end as _),
                     &fallback[fallback.len().min(1)..],
+                    piece_start,
+                    dropout.as_deref_mut(),
                 )?;
             } else if fallback.first() == Some(&Fallback::Unknown) && self.unknown.is_some() {
-                result.push(self.unknown.as_ref().unwrap().id);
+                result.push_token(self.unknown.as_ref().unwrap().id, piece_start, piece_end);
             } else if fallback.first() == Some(&Fallback::Skip) {
             } else {
                 return Err(EncodeError::InvalidPiece(piece.into()));
@@ -366,19 +483,65 @@ impl BytePair {
             }
         }
     }
+
+    /// Merges the given parts according to the BPE algorithm, randomly skipping merges with the dropout probability.
+    ///
+    /// Skipped merges are reconsidered after the next merge. Merging stops when every remaining merge is skipped.
+    #[inline(never)]
+    #[cold]
+    fn merge_bpe_parts_dropout(
+        piece: &[u8], parts: &mut Vec<RankedPart>, start: usize, ranks: &RankMap,
+        dropout: &mut Dropout,
+    ) {
+        if parts.len() <= start + 1 {
+            return;
+        }
+        for j in start..parts.len() - 1 {
+            parts[j].rank = BytePair::get_rank(piece, &parts[..], j, j + 2, ranks);
+        }
+        // flags of the merges skipped since the last merge, indexed like the parts
+        let mut skipped = Vec::new();
+        skipped.resize(parts.len(), false);
+        loop {
+            let mut min_score = TokenRank::MAX;
+            let mut i = start;
+            for j in start..parts.len() - 1 {
+                if parts[j].rank < min_score && !skipped[j] {
+                    (min_score, i) = (parts[j].rank, j);
+                }
+            }
+            if min_score == TokenRank::MAX {
+                break;
+            }
+            if dropout.skip() {
+                skipped[i] = true;
+                continue;
+            }
+            if i > start {
+                parts[i - 1].rank = BytePair::get_rank(piece, parts, i - 1, i + 2, ranks);
+            }
+            parts[i].rank = BytePair::get_rank(piece, parts, i, i + 3, ranks);
+            parts.remove(i + 1);
+            skipped.truncate(parts.len());
+            skipped.fill(false);
+        }
+    }
 }
 impl BytePair {
     /// Encodes the given piece into a sequence of tokens using the BPE algorithm.
     ///
     /// This version uses a heap for tracking the merge candidates.
     ///
+    /// `offset` is the byte offset of the piece in the normalized input.
+    ///
     /// Returns an error if no token for a part exists in the encoder, no unknown token id is set in the configuration, and no fallback is set.
     #[inline(never)]
     #[cold]
+    #[allow(clippy::too_many_arguments)]
     fn encode_pairs_heap(
         &self, piece: &[u8], buffer: &mut Vec<RankedPart>, heap: &mut PieceHeap,
-        result: &mut Vec<TokenId>, indices: impl Iterator<Item = (u32, u32)>,
-        fallback: &[Fallback],
+        result: &mut impl EncodeOutput, indices: impl Iterator<Item = (u32, u32)>,
+        fallback: &[Fallback], offset: usize, mut dropout: Option<&mut Dropout>,
     ) -> Result<(), EncodeError> {
         if piece.len() > heap.index_bound() {
             *heap = PieceHeap::with_index_bound(piece.len());
@@ -417,13 +580,19 @@ impl BytePair {
             });
             prior = e as _;
         }
-        BytePair::merge_bpe_parts_heap(piece, heap, &self.ranks);
+        if let Some(dropout) = dropout.as_deref_mut() {
+            BytePair::merge_bpe_parts_heap_dropout(piece, heap, &self.ranks, dropout);
+        } else {
+            BytePair::merge_bpe_parts_heap(piece, heap, &self.ranks);
+        }
         let mut e = 0;
         while e <= prior {
             let part = heap.key_of(&e).unwrap();
+            let piece_start = offset + part.start as usize;
+            let piece_end = offset + (part.start + part.width) as usize;
             let piece = &piece[part.start as _..(part.start + part.width) as _];
             if let Some(&token) = self.vocab.get(piece) {
-                result.push(token);
+                result.push_token(token, piece_start, piece_end);
             } else if fallback.first() == Some(&Fallback::Bytes) {
                 let end = if let Some(end_of_word) = &self.end_of_word {
                     piece.len() - end_of_word.len()
@@ -436,9 +605,11 @@ impl BytePair {
                     result,
                     (0..end).map(|i| i as u32),
                     &fallback[fallback.len().min(1)..],
+                    piece_start,
+                    dropout.as_deref_mut(),
                 )?;
             } else if fallback.first() == Some(&Fallback::Unknown) && self.unknown.is_some() {
-                result.push(self.unknown.as_ref().unwrap().id);
+                result.push_token(self.unknown.as_ref().unwrap().id, piece_start, piece_end);
             } else if fallback.first() == Some(&Fallback::Skip) {
             } else {
                 return Err(EncodeError::InvalidPiece(piece.into()));
@@ -500,4 +671,57 @@ impl BytePair {
             heap.update_key(&i, part);
         }
     }
+
+    /// Merges the given parts according to the BPE algorithm, randomly skipping merges with the dropout probability.
+    ///
+    /// This version uses a heap for tracking the merge candidates.
+    /// Skipped merges are reconsidered after the next merge. Merging stops when every remaining merge is skipped.
+    #[inline(never)]
+    #[cold]
+    fn merge_bpe_parts_heap_dropout(
+        piece: &[u8], heap: &mut PieceHeap, ranks: &RankMap, dropout: &mut Dropout,
+    ) {
+        let rank_of = |heap: &PieceHeap, part: &LinkedPart| {
+            if part.after == u32::MAX {
+                return TokenRank::MAX;
+            }
+            let next = heap.key_of(&part.after).unwrap();
+            let piece = &piece[part.start as _..(next.start + next.width) as _];
+            ranks.get(piece).copied().unwrap_or(TokenRank::MAX)
+        };
+        let mut skipped = Vec::new();
+        while heap.len() > 1 {
+            let &(i, mut part) = heap.peek().unwrap();
+            if part.rank == TokenRank::MAX {
+                break;
+            }
+            if dropout.skip() {
+                part.rank = TokenRank::MAX;
+                heap.update_key(&i, part);
+                skipped.push(i);
+                continue;
+            }
+            let next = heap.remove(&part.after);
+            part.width += next.width;
+            part.after = next.after;
+            if part.after != u32::MAX {
+                let mut next = heap.key_of(&part.after).unwrap();
+                next.prior = i;
+                heap.update_key(&part.after, next);
+            }
+            part.rank = rank_of(heap, &part);
+            heap.update_key(&i, part);
+            if part.prior != u32::MAX {
+                let mut prior = heap.key_of(&part.prior).unwrap();
+                prior.rank = rank_of(heap, &prior);
+                heap.update_key(&part.prior, prior);
+            }
+            for j in skipped.drain(..) {
+                if let Some(mut part) = heap.key_of(&j) {
+                    part.rank = rank_of(heap, &part);
+                    heap.update_key(&j, part);
+                }
+            }
+        }
+    }
 }
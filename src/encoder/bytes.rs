@@ -0,0 +1,177 @@
+//! Byte encoder.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{
+    EncodeBuffers, EncodeError, EncodeOutput, Encoder, Encoding, InitializationError, Model,
+    TextPart, Token, TokenId,
+};
+
+/// Byte encoder.
+///
+/// Encodes each byte of the input into the token with the id `byte + offset`, like ByT5.
+#[derive(Debug, Clone)]
+pub(crate) struct Bytes {
+    offset: TokenId,
+}
+impl Encoder for Bytes {
+    #[inline(always)]
+    fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError> {
+        let mut result = Vec::with_capacity(text.len());
+        self.encode_bytes(parts, &mut result);
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result = Encoding::with_capacity(text.len());
+        self.encode_bytes(parts, &mut result);
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        Ok(parts
+            .iter()
+            .map(|part| {
+                if part.special != Token::INVALID {
+                    1
+                } else {
+                    part.len()
+                }
+            })
+            .sum())
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, _buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        self.encode_bytes(parts, result);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        match bytes {
+            [byte] => Some(*byte as TokenId + self.offset),
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    fn model(&self) -> Model {
+        Model::Bytes {
+            offset: self.offset,
+        }
+    }
+}
+impl Bytes {
+    #[inline(never)]
+    pub fn new(offset: TokenId) -> Result<Self, InitializationError> {
+        if offset.checked_add(u8::MAX as TokenId).is_none() {
+            return Err(InitializationError::InvalidEncoder);
+        }
+        Ok(Self { offset })
+    }
+
+    /// Encodes the given parts into a sequence of tokens with one token per byte.
+    #[inline(never)]
+    fn encode_bytes(&self, parts: &[TextPart], result: &mut impl EncodeOutput) {
+        for part in parts {
+            if part.special != Token::INVALID {
+                result.push_token(part.special, part.start, part.start + part.len());
+                continue;
+            }
+            for (i, &byte) in part.iter().enumerate() {
+                let start = part.start + i;
+                result.push_token(byte as TokenId + self.offset, start, start + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use crate::{
+        Configuration, Definition, DefinitionError, InitializationError, Kitoken, Metadata, Model,
+        SpecialToken, SpecialTokenKind, SpecialVocab,
+    };
+
+    fn specials() -> SpecialVocab {
+        ["<pad>", "</s>", "<unk>"]
+            .into_iter()
+            .zip(0..)
+            .map(|(bytes, id)| SpecialToken {
+                id,
+                bytes: bytes.as_bytes().to_vec(),
+                kind: SpecialTokenKind::Control,
+                ident: Some(bytes.trim_matches(['<', '/', '>']).to_string()),
+                score: 0.0,
+                extract: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let model = Model::Bytes { offset: 3 };
+        let config = Configuration::default();
+        let tokenizer = Kitoken::new(model, specials(), config, Metadata::default()).unwrap();
+        let tokens = tokenizer.encode("Hi é</s>", true).unwrap();
+        assert_eq!(tokens, [75, 108, 35, 198, 172, 1]);
+        assert_eq!(tokenizer.decode(&tokens, true).unwrap(), "Hi é</s>".as_bytes());
+        assert_eq!(tokenizer.decode(&tokens, false).unwrap(), "Hi é".as_bytes());
+        assert!(tokenizer.decode([259], true).is_err());
+        assert_eq!(tokenizer.count_tokens("Hi é</s>", true).unwrap(), tokens.len());
+        assert_eq!(tokenizer.token_to_id("H"), Some(75));
+        assert_eq!(tokenizer.id_to_token(75).as_deref(), Some(&b"H"[..]));
+        assert_eq!(tokenizer.id_to_token(1).as_deref(), Some(&b"</s>"[..]));
+        assert_eq!(tokenizer.vocab_size(), 259);
+
+        let definition = tokenizer.to_definition();
+        assert_eq!(definition.model, Model::Bytes { offset: 3 });
+        assert_eq!(definition.next_token_id(), 259);
+        #[cfg(feature = "serialization")]
+        {
+            let definition = Definition::from_slice(&definition.to_vec()).unwrap();
+            let tokenizer = Kitoken::from_definition(definition).unwrap();
+            assert_eq!(tokenizer.encode("Hi é</s>", true).unwrap(), tokens);
+        }
+    }
+
+    #[test]
+    fn test_invalid_offset() {
+        let model = Model::Bytes { offset: u32::MAX };
+        let config = Configuration::default();
+        assert!(Kitoken::new(model, specials(), config, Metadata::default()).is_err());
+    }
+
+    #[test]
+    fn test_special_collision() {
+        let model = Model::Bytes { offset: 2 };
+        let config = Configuration::default();
+        assert!(matches!(
+            Kitoken::new(model, specials(), config, Metadata::default()),
+            Err(InitializationError::SpecialTokenCollision(2))
+        ));
+    }
+
+    #[test]
+    fn test_definition_vocab() {
+        let model = Model::Bytes { offset: 3 };
+        let config = Configuration::default();
+        let tokenizer = Kitoken::new(model, specials(), config, Metadata::default()).unwrap();
+        let mut definition = tokenizer.to_definition();
+        assert!(definition.model.vocab().is_empty());
+        assert!(definition.model.try_vocab_mut().is_none());
+        assert_eq!(definition.model.vocab_size(), 256);
+        assert!(matches!(
+            definition.extend_vocab(["Hi"]),
+            Err(DefinitionError::UnsupportedModel)
+        ));
+    }
+}
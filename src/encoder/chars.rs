@@ -0,0 +1,143 @@
+//! Character encoder.
+
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use crate::{
+    EncodeBuffers, EncodeError, EncodeOutput, Encoder, Encoding, Model, TextPart, Token, TokenId,
+};
+
+/// Character encoder.
+///
+/// Encodes each character of the input into the token with the id of its codepoint, like CANINE.
+#[derive(Debug, Clone)]
+pub(crate) struct Chars;
+impl Encoder for Chars {
+    #[inline(always)]
+    fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError> {
+        let mut result = Vec::with_capacity(text.len());
+        self.encode_chars(parts, &mut result);
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result = Encoding::with_capacity(text.len());
+        self.encode_chars(parts, &mut result);
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        Ok(parts
+            .iter()
+            .map(|part| {
+                if part.special != Token::INVALID {
+                    1
+                } else {
+                    part.text.chars().count()
+                }
+            })
+            .sum())
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, _buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        self.encode_chars(parts, result);
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        let mut chars = core::str::from_utf8(bytes).ok()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(char), None) => Some(char as TokenId),
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    fn model(&self) -> Model {
+        Model::Chars
+    }
+}
+impl Chars {
+    /// Encodes the given parts into a sequence of tokens with one token per character.
+    #[inline(never)]
+    fn encode_chars(&self, parts: &[TextPart], result: &mut impl EncodeOutput) {
+        for part in parts {
+            if part.special != Token::INVALID {
+                result.push_token(part.special, part.start, part.start + part.len());
+                continue;
+            }
+            for (i, char) in part.text.char_indices() {
+                let start = part.start + i;
+                result.push_token(char as TokenId, start, start + char.len_utf8());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        Configuration, Definition, InitializationError, Kitoken, Metadata, Model, SpecialToken,
+        SpecialTokenKind,
+    };
+
+    #[test]
+    fn test_encode_decode() {
+        let specials = Vec::from([SpecialToken {
+            id:      0x110000,
+            bytes:   b"[CLS]".to_vec(),
+            kind:    SpecialTokenKind::Control,
+            ident:   None,
+            score:   0.0,
+            extract: false,
+        }]);
+        let config = Configuration::default();
+        let tokenizer = Kitoken::new(Model::Chars, specials, config, Metadata::default()).unwrap();
+        let tokens = tokenizer.encode("[CLS]Hé😀", true).unwrap();
+        assert_eq!(tokens, [0x110000, 'H' as u32, 'é' as u32, '😀' as u32]);
+        assert_eq!(tokenizer.decode(&tokens, true).unwrap(), "[CLS]Hé😀".as_bytes());
+        assert!(tokenizer.decode([0xD800], true).is_err());
+        let encoding = tokenizer.encode_with_offsets("Hé😀", true).unwrap();
+        assert_eq!(encoding.offsets, [(0, 1), (1, 3), (3, 7)]);
+        assert_eq!(tokenizer.count_tokens("Hé😀", true).unwrap(), 3);
+        assert_eq!(tokenizer.token_to_id("😀"), Some('😀' as u32));
+        assert_eq!(tokenizer.token_to_id("Hé"), None);
+        assert_eq!(tokenizer.id_to_token('é' as u32).as_deref(), Some("é".as_bytes()));
+        assert_eq!(tokenizer.id_to_token(0x110000).as_deref(), Some(&b"[CLS]"[..]));
+        assert_eq!(tokenizer.vocab_size(), 0x110000 - 0x800 + 1);
+
+        let definition = tokenizer.to_definition();
+        assert_eq!(definition.model, Model::Chars);
+        #[cfg(feature = "serialization")]
+        {
+            let definition = Definition::from_slice(&definition.to_vec()).unwrap();
+            let tokenizer = Kitoken::from_definition(definition).unwrap();
+            assert_eq!(tokenizer.encode("[CLS]Hé😀", true).unwrap(), tokens);
+        }
+    }
+
+    #[test]
+    fn test_special_collision() {
+        let specials = Vec::from([SpecialToken {
+            id:      0xE000,
+            bytes:   b"[CLS]".to_vec(),
+            kind:    SpecialTokenKind::Control,
+            ident:   None,
+            score:   0.0,
+            extract: false,
+        }]);
+        let config = Configuration::default();
+        assert!(matches!(
+            Kitoken::new(Model::Chars, specials, config, Metadata::default()),
+            Err(InitializationError::SpecialTokenCollision(0xE000))
+        ));
+    }
+}
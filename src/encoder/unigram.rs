@@ -9,9 +9,9 @@ use bstr::ByteSlice;
 use hashbrown::HashMap;
 
 use crate::{
-    Configuration, EncodeError, Encoder, Fallback, InitializationError, Model, Scores,
-    SpecialToken, SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, TokenScore,
-    Vocab,
+    Configuration, EncodeBuffers, EncodeError, EncodeOutput, Encoder, Encoding, Fallback,
+    InitializationError, Model, Rng, ScoredEncoder, Scores, Segmentation, SpecialToken,
+    SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, TokenScore, Vocab,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -28,6 +28,13 @@ struct SizedPart {
     pub token: TokenId,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    pub start: usize,
+    pub token: TokenId,
+    pub score: f64,
+}
+
 type ScoredVocabMap = HashMap<TokenBytes, ScoredToken>;
 
 /// Unigram encoder.
@@ -40,6 +47,7 @@ pub(crate) struct Unigram {
 
     max_token_bytes: usize,
     min_token_bytes: usize,
+    min_score:       f64,
 }
 impl Debug for Unigram {
     #[inline(never)]
@@ -50,6 +58,7 @@ impl Debug for Unigram {
             .field("fallback", &self.fallback)
             .field("max_token_bytes", &self.max_token_bytes)
             .field("min_token_bytes", &self.min_token_bytes)
+            .field("min_score", &self.min_score)
             .finish()
     }
 }
@@ -58,10 +67,42 @@ impl Encoder for Unigram {
     fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError> {
         let mut result =
             Vec::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
-        self.encode_chars(parts, &self.fallback, &mut result)?;
+        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
+        self.encode_chars(parts, &self.fallback, &mut result, &mut buffer)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result =
+            Encoding::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
+        self.encode_chars(parts, &self.fallback, &mut result, &mut buffer)?;
         Ok(result)
     }
 
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        let mut result = 0;
+        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
+        self.encode_chars(parts, &self.fallback, &mut result, &mut buffer)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        let buffer = buffers.get_or_default::<Vec<SizedPart>>();
+        buffer.clear();
+        self.encode_chars(parts, &self.fallback, result, buffer)
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        self.vocab.get(bytes).map(|token| token.id)
+    }
+
     #[inline(always)]
     fn model(&self) -> Model {
         let mut vocab = self.vocab.iter().map(|(k, v)| (k.clone(), *v)).collect::<Vec<_>>();
@@ -73,6 +114,75 @@ impl Encoder for Unigram {
         let vocab = vocab.into_iter().map(|(k, v)| (v.id, k).into()).collect();
         Model::Unigram { vocab, scores }
     }
+
+    #[inline(always)]
+    fn as_scored(&self) -> Option<&dyn ScoredEncoder> {
+        Some(self)
+    }
+}
+impl ScoredEncoder for Unigram {
+    #[inline(always)]
+    fn encode_nbest(
+        &self, parts: &mut [TextPart], n: usize,
+    ) -> Result<Vec<Segmentation>, EncodeError> {
+        let mut result = Vec::from([Segmentation::default()]);
+        result.truncate(n);
+        for part in parts.iter() {
+            let segmentations = if part.special != Token::INVALID {
+                Vec::from([Segmentation {
+                    tokens:   Vec::from([part.special]),
+                    log_prob: 0.0,
+                }])
+            } else {
+                self.nbest_piece(part.as_bytes(), n)?
+            };
+            result = combine_nbest(&result, &segmentations, n);
+        }
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_sample(
+        &self, parts: &mut [TextPart], alpha: f32, nbest: usize, rng: &mut Rng,
+    ) -> Result<Segmentation, EncodeError> {
+        let alpha = alpha as f64;
+        if nbest == 0 {
+            let mut result = Segmentation::default();
+            for part in parts.iter() {
+                if part.special != Token::INVALID {
+                    result.tokens.push(part.special);
+                    continue;
+                }
+                let sampled = self.sample_piece(part.as_bytes(), alpha, rng)?;
+                result.tokens.extend(sampled.tokens);
+                result.log_prob += sampled.log_prob;
+            }
+            return Ok(result);
+        }
+        let mut segmentations = self.encode_nbest(parts, nbest)?;
+        let total = log_sum_exp(segmentations.iter().map(|s| alpha * s.log_prob));
+        let mut target = rng.next_f64();
+        let mut chosen = segmentations.len() - 1;
+        for (i, segmentation) in segmentations.iter().enumerate() {
+            target -= libm::exp(alpha * segmentation.log_prob - total);
+            if target < 0.0 {
+                chosen = i;
+                break;
+            }
+        }
+        Ok(segmentations.swap_remove(chosen))
+    }
+
+    #[inline(always)]
+    fn token_score(&self, id: TokenId, bytes: &[u8]) -> Option<f64> {
+        if self.unknown.as_ref().is_some_and(|unknown| unknown.id == id) {
+            return Some(self.min_score - Self::UNKNOWN_PENALTY);
+        }
+        self.vocab
+            .get(bytes)
+            .filter(|token| token.id == id)
+            .map(|token| token.score as f64)
+    }
 }
 impl Unigram {
     const ENCODE_BUFFER_SIZE: usize = 256;
@@ -107,6 +217,7 @@ impl Unigram {
 
         let max_token_bytes = vocab.keys().map(|k| k.len()).max().unwrap().max(1);
         let min_token_bytes = vocab.keys().map(|k| k.len()).min().unwrap().max(1);
+        let min_score = vocab.values().map(|t| t.score as f64).fold(f64::INFINITY, f64::min);
 
         let fallback = config.fallback.clone();
 
@@ -116,26 +227,28 @@ impl Unigram {
             fallback,
             max_token_bytes,
             min_token_bytes,
+            min_score,
         })
     }
 
     /// Encodes the given parts into a sequence of tokens starting at individual characters.
     #[inline(never)]
     fn encode_chars(
-        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut Vec<TokenId>,
+        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut impl EncodeOutput,
+        buffer: &mut Vec<SizedPart>,
     ) -> Result<(), EncodeError> {
-        let mut buffer = Vec::with_capacity(Self::ENCODE_BUFFER_SIZE);
         for part in parts {
             if part.special != Token::INVALID {
-                result.push(part.special);
+                result.push_token(part.special, part.start, part.start + part.len());
                 continue;
             }
             self.encode_unigram(
                 part.as_bytes(),
-                &mut buffer,
+                buffer,
                 result,
                 part.char_indices().map(|(i, _, _)| i),
                 fallback,
+                part.start,
             )?;
             buffer.clear();
         }
@@ -145,11 +258,13 @@ impl Unigram {
     /// Encodes the given piece into a sequence of tokens using the unigram algorithm.
     /// This algorithm merges the highest scored subword units.
     ///
+    /// `offset` is the byte offset of the piece in the normalized input.
+    ///
     /// Returns an error if no token for a part exists in the encoder, no unknown token id is set in the configuration, and no fallback is set.
     #[inline(never)]
     fn encode_unigram(
-        &self, piece: &[u8], buffer: &mut Vec<SizedPart>, result: &mut Vec<TokenId>,
-        indices: impl Iterator<Item = usize>, fallback: &[Fallback],
+        &self, piece: &[u8], buffer: &mut Vec<SizedPart>, result: &mut impl EncodeOutput,
+        indices: impl Iterator<Item = usize>, fallback: &[Fallback], offset: usize,
     ) -> Result<(), EncodeError> {
         let start = buffer.len();
         buffer.extend(indices.map(|c| SizedPart {
@@ -165,21 +280,27 @@ impl Unigram {
             token: Token::INVALID,
         });
         Unigram::merge_parts(piece, buffer, &self.vocab, start, self.max_token_bytes);
-        let result_start = result.len();
+        let result_start = result.token_count();
         let mut sub_end = buffer.len() - 1;
         while sub_end > start {
+            let part_end = offset + buffer[sub_end].start;
             if buffer[sub_end].token == Token::INVALID {
+                let part_start = offset + buffer[sub_end - 1].start;
                 if fallback.first() == Some(&Fallback::Bytes) {
                     let part = &piece[buffer[sub_end - 1].start..buffer[sub_end].start];
+                    let bytes_start = result.token_count();
                     self.encode_unigram(
                         part,
                         buffer,
                         result,
                         0..part.len(),
                         &fallback[fallback.len().min(1)..],
+                        part_start,
                     )?;
+                    // the byte tokens are in order and are reversed again with the rest of the piece
+                    result.reverse_tokens(bytes_start);
                 } else if fallback.first() == Some(&Fallback::Unknown) && self.unknown.is_some() {
-                    result.push(self.unknown.as_ref().unwrap().id);
+                    result.push_token(self.unknown.as_ref().unwrap().id, part_start, part_end);
                 } else if fallback.first() == Some(&Fallback::Skip) {
                 } else {
                     let part = &piece[buffer[sub_end - 1].start..buffer[sub_end].start];
@@ -188,10 +309,11 @@ impl Unigram {
                 sub_end -= buffer[sub_end].width;
                 continue;
             }
-            result.push(buffer[sub_end].token);
+            let part_start = offset + buffer[sub_end - buffer[sub_end].width].start;
+            result.push_token(buffer[sub_end].token, part_start, part_end);
             sub_end -= buffer[sub_end].width;
         }
-        result[result_start..].reverse();
+        result.reverse_tokens(result_start);
         Ok(())
     }
 
@@ -231,3 +353,209 @@ impl Unigram {
         }
     }
 }
+impl Unigram {
+    /// Score penalty of unknown characters relative to the lowest token score, like in SentencePiece.
+    const UNKNOWN_PENALTY: f64 = 10.0;
+
+    /// Builds the segmentation lattice of the given piece.
+    ///
+    /// Returns the byte offset of each character boundary, and the edges ending at each boundary.
+    /// Characters without a token are covered by edges with [`Token::INVALID`] scored with the unknown penalty.
+    #[inline(never)]
+    fn lattice(&self, piece: &[u8]) -> (Vec<usize>, Vec<Vec<Edge>>) {
+        let mut bounds = piece.char_indices().map(|(i, _, _)| i).collect::<Vec<_>>();
+        bounds.push(piece.len());
+        let mut edges = Vec::with_capacity(bounds.len());
+        edges.push(Vec::new());
+        for end in 1..bounds.len() {
+            let mut ending = Vec::new();
+            let mut covered = false;
+            for start in (0..end).rev() {
+                if bounds[end] - bounds[start] > self.max_token_bytes {
+                    break;
+                }
+                if let Some(token) = self.vocab.get(&piece[bounds[start]..bounds[end]]) {
+                    covered |= start == end - 1;
+                    ending.push(Edge {
+                        start,
+                        token: token.id,
+                        score: token.score as f64,
+                    });
+                }
+            }
+            if !covered {
+                ending.push(Edge {
+                    start: end - 1,
+                    token: Token::INVALID,
+                    score: self.min_score - Self::UNKNOWN_PENALTY,
+                });
+            }
+            edges.push(ending);
+        }
+        (bounds, edges)
+    }
+
+    /// Pushes the tokens of the given edge path of a piece to `result`, resolving unknown characters with the fallback.
+    #[inline(never)]
+    fn push_path(
+        &self, piece: &[u8], bounds: &[usize], path: &[Edge], ends: &[usize],
+        result: &mut Vec<TokenId>,
+    ) -> Result<(), EncodeError> {
+        for (edge, &end) in path.iter().zip(ends) {
+            if edge.token != Token::INVALID {
+                result.push(edge.token);
+                continue;
+            }
+            let bytes = &piece[bounds[edge.start]..bounds[end]];
+            let mut resolved = false;
+            for fallback in &self.fallback {
+                match fallback {
+                    Fallback::Bytes => {
+                        let tokens = bytes
+                            .iter()
+                            .map(|byte| self.vocab.get(core::slice::from_ref(byte)).map(|t| t.id))
+                            .collect::<Option<Vec<_>>>();
+                        if let Some(tokens) = tokens {
+                            result.extend(tokens);
+                            resolved = true;
+                        }
+                    }
+                    Fallback::Unknown => {
+                        if let Some(unknown) = &self.unknown {
+                            result.push(unknown.id);
+                            resolved = true;
+                        }
+                    }
+                    Fallback::Skip => resolved = true,
+                }
+                if resolved {
+                    break;
+                }
+            }
+            if !resolved {
+                return Err(EncodeError::InvalidPiece(bytes.to_vec()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `n` best segmentations of the given piece, ordered from best to worst.
+    #[inline(never)]
+    fn nbest_piece(&self, piece: &[u8], n: usize) -> Result<Vec<Segmentation>, EncodeError> {
+        #[derive(Clone, Copy)]
+        struct Candidate {
+            score: f64,
+            edge:  usize,
+            rank:  usize,
+        }
+        let (bounds, edges) = self.lattice(piece);
+        let mut best = Vec::with_capacity(bounds.len());
+        best.push(Vec::from([Candidate {
+            score: 0.0,
+            edge:  usize::MAX,
+            rank:  0,
+        }]));
+        for ending in edges.iter().skip(1) {
+            let mut candidates = Vec::new();
+            for (e, edge) in ending.iter().enumerate() {
+                for (rank, prior) in best[edge.start].iter().enumerate() {
+                    candidates.push(Candidate {
+                        score: prior.score + edge.score,
+                        edge: e,
+                        rank,
+                    });
+                }
+            }
+            candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+            candidates.truncate(n);
+            best.push(candidates);
+        }
+        let last = bounds.len() - 1;
+        let mut result = Vec::with_capacity(best[last].len());
+        for candidate in &best[last] {
+            let (mut path, mut ends) = (Vec::new(), Vec::new());
+            let (mut end, mut candidate) = (last, *candidate);
+            while end > 0 {
+                let edge = edges[end][candidate.edge];
+                path.push(edge);
+                ends.push(end);
+                candidate = best[edge.start][candidate.rank];
+                end = edge.start;
+            }
+            path.reverse();
+            ends.reverse();
+            let mut tokens = Vec::with_capacity(path.len());
+            self.push_path(piece, &bounds, &path, &ends, &mut tokens)?;
+            let log_prob = path.iter().map(|edge| edge.score).sum();
+            result.push(Segmentation { tokens, log_prob });
+        }
+        Ok(result)
+    }
+
+    /// Samples a segmentation of the given piece from all segmentations using forward-filtering and backward-sampling.
+    #[inline(never)]
+    fn sample_piece(
+        &self, piece: &[u8], alpha: f64, rng: &mut Rng,
+    ) -> Result<Segmentation, EncodeError> {
+        let (bounds, edges) = self.lattice(piece);
+        let mut forward = Vec::with_capacity(bounds.len());
+        forward.push(0.0);
+        for ending in edges.iter().skip(1) {
+            let weights = ending.iter().map(|edge| forward[edge.start] + alpha * edge.score);
+            forward.push(log_sum_exp(weights));
+        }
+        let (mut path, mut ends) = (Vec::new(), Vec::new());
+        let mut end = bounds.len() - 1;
+        while end > 0 {
+            let ending = &edges[end];
+            let mut target = rng.next_f64();
+            let mut chosen = ending[ending.len() - 1];
+            for edge in ending {
+                target -= libm::exp(forward[edge.start] + alpha * edge.score - forward[end]);
+                if target < 0.0 {
+                    chosen = *edge;
+                    break;
+                }
+            }
+            path.push(chosen);
+            ends.push(end);
+            end = chosen.start;
+        }
+        path.reverse();
+        ends.reverse();
+        let mut tokens = Vec::with_capacity(path.len());
+        self.push_path(piece, &bounds, &path, &ends, &mut tokens)?;
+        let log_prob = path.iter().map(|edge| edge.score).sum();
+        Ok(Segmentation { tokens, log_prob })
+    }
+}
+
+/// Returns the logarithm of the sum of the exponentials of the given values.
+#[inline(always)]
+fn log_sum_exp(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + libm::log(values.map(|value| libm::exp(value - max)).sum::<f64>())
+}
+
+/// Combines the best segmentations of consecutive parts into the `n` best segmentations of both parts.
+#[inline(never)]
+fn combine_nbest(left: &[Segmentation], right: &[Segmentation], n: usize) -> Vec<Segmentation> {
+    let mut pairs = Vec::with_capacity(left.len() * right.len());
+    for (i, a) in left.iter().enumerate() {
+        for (j, b) in right.iter().enumerate() {
+            pairs.push((a.log_prob + b.log_prob, i, j));
+        }
+    }
+    pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+    pairs.truncate(n);
+    pairs
+        .into_iter()
+        .map(|(log_prob, i, j)| Segmentation {
+            tokens: [&left[i].tokens[..], &right[j].tokens[..]].concat(),
+            log_prob,
+        })
+        .collect()
+}
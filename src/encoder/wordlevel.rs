@@ -0,0 +1,170 @@
+//! WordLevel encoder.
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use bstr::ByteSlice;
+use hashbrown::HashMap;
+
+use crate::{
+    Configuration, EncodeBuffers, EncodeError, EncodeOutput, Encoder, Encoding, Fallback, Model,
+    SpecialToken, SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, Vocab,
+};
+
+type VocabMap = HashMap<TokenBytes, TokenId>;
+
+/// WordLevel encoder.
+#[derive(Clone)]
+pub(crate) struct WordLevel {
+    vocab: VocabMap,
+
+    unknown:  Option<SpecialToken>,
+    fallback: Vec<Fallback>,
+
+    max_token_bytes: usize,
+    min_token_bytes: usize,
+}
+impl Debug for WordLevel {
+    #[inline(never)]
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("WordLevel")
+            .field("vocab", &format!("VocabMap({})", self.vocab.len()))
+            .field("unknown", &self.unknown)
+            .field("fallback", &self.fallback)
+            .field("max_token_bytes", &self.max_token_bytes)
+            .field("min_token_bytes", &self.min_token_bytes)
+            .finish()
+    }
+}
+impl Encoder for WordLevel {
+    #[inline(always)]
+    fn encode(&self, text: &str, parts: &mut [TextPart]) -> Result<Vec<TokenId>, EncodeError> {
+        let mut result =
+            Vec::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        self.encode_words(parts, &mut result)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result =
+            Encoding::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        self.encode_words(parts, &mut result)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        let mut result = 0;
+        self.encode_words(parts, &mut result)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, _buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        self.encode_words(parts, result)
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        self.vocab.get(bytes).copied()
+    }
+
+    #[inline(always)]
+    fn model(&self) -> Model {
+        let mut vocab = self.vocab.iter().map(|(k, v)| (k.clone(), *v).into()).collect::<Vocab>();
+        vocab.sort_by(|Token { bytes: a, id: ai }, Token { bytes: b, id: bi }| {
+            let comp = ai.cmp(bi);
+            if comp == Ordering::Equal {
+                a.cmp(b)
+            } else {
+                comp
+            }
+        });
+        Model::WordLevel { vocab }
+    }
+}
+impl WordLevel {
+    #[inline(never)]
+    pub fn new(vocab: Vocab, specials: &SpecialVocab, config: &Configuration) -> Self {
+        let unknown = specials
+            .iter()
+            .find(|special| special.kind == SpecialTokenKind::Unknown)
+            .cloned();
+
+        let vocab = vocab.iter().map(|token| token.into()).collect::<VocabMap>();
+
+        let max_token_bytes = vocab.keys().map(|k| k.len()).max().unwrap_or_default().max(1);
+        let min_token_bytes = vocab.keys().map(|k| k.len()).min().unwrap_or_default().max(1);
+
+        let fallback = config.fallback.clone();
+
+        Self {
+            vocab,
+            unknown,
+            fallback,
+            max_token_bytes,
+            min_token_bytes,
+        }
+    }
+
+    /// Encodes the given parts into a sequence of tokens with one token per part.
+    #[inline(never)]
+    fn encode_words(
+        &self, parts: &[TextPart], result: &mut impl EncodeOutput,
+    ) -> Result<(), EncodeError> {
+        for part in parts {
+            let (start, end) = (part.start, part.start + part.len());
+            if part.special != Token::INVALID {
+                result.push_token(part.special, start, end);
+                continue;
+            }
+            if part.is_empty() {
+                continue;
+            }
+            if let Some(&token) = self.vocab.get(part.as_bytes()) {
+                result.push_token(token, start, end);
+                continue;
+            }
+            self.encode_fallback(part.as_bytes(), result, start)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the given bytes of a part without a token using the fallback.
+    ///
+    /// `offset` is the byte offset of the bytes in the normalized input.
+    #[inline(never)]
+    fn encode_fallback(
+        &self, bytes: &[u8], result: &mut impl EncodeOutput, offset: usize,
+    ) -> Result<(), EncodeError> {
+        for fallback in &self.fallback {
+            match fallback {
+                Fallback::Bytes => {
+                    let tokens = bytes
+                        .iter()
+                        .map(|byte| self.vocab.get(core::slice::from_ref(byte)).copied())
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(tokens) = tokens {
+                        for (i, token) in tokens.into_iter().enumerate() {
+                            result.push_token(token, offset + i, offset + i + 1);
+                        }
+                        return Ok(());
+                    }
+                }
+                Fallback::Unknown => {
+                    if let Some(unknown) = &self.unknown {
+                        result.push_token(unknown.id, offset, offset + bytes.len());
+                        return Ok(());
+                    }
+                }
+                Fallback::Skip => return Ok(()),
+            }
+        }
+        Err(EncodeError::InvalidPiece(bytes.to_vec()))
+    }
+}
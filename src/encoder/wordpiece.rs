@@ -11,8 +11,9 @@ use bstr::ByteSlice;
 use hashbrown::HashMap;
 
 use crate::{
-    Configuration, EncodeError, Encoder, Fallback, InsertionPosition, Model, SpecialToken,
-    SpecialTokenKind, SpecialVocab, TextPart, Token, TokenBytes, TokenId, Vocab,
+    Configuration, EncodeBuffers, EncodeError, EncodeOutput, Encoder, Encoding, Fallback,
+    InsertionPosition, Model, SpecialToken, SpecialTokenKind, SpecialVocab, TextPart, Token,
+    TokenBytes, TokenId, Vocab,
 };
 
 type VocabMap = HashMap<TokenBytes, TokenId>;
@@ -55,6 +56,39 @@ impl Encoder for WordPiece {
         Ok(result)
     }
 
+    #[inline(always)]
+    fn encode_offsets(&self, text: &str, parts: &mut [TextPart]) -> Result<Encoding, EncodeError> {
+        let mut result =
+            Encoding::with_capacity(text.len() / self.min_token_bytes + self.max_token_bytes);
+        self.encode_chars(parts, &self.fallback, &mut result)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn count(&self, _text: &str, parts: &mut [TextPart]) -> Result<usize, EncodeError> {
+        let mut result = 0;
+        self.encode_chars(parts, &self.fallback, &mut result)?;
+        Ok(result)
+    }
+
+    #[inline(always)]
+    fn encode_into(
+        &self, parts: &mut [TextPart], result: &mut Vec<TokenId>, _buffers: &mut EncodeBuffers,
+    ) -> Result<(), EncodeError> {
+        self.encode_chars(parts, &self.fallback, result)
+    }
+
+    #[inline(always)]
+    fn token_id(&self, bytes: &[u8]) -> Option<TokenId> {
+        if let Some(subword_prefix) = &self.subword_prefix
+            && let Some(continuation) = bytes.strip_prefix(subword_prefix.as_bytes())
+            && let Some(&id) = self.continuation.get(continuation)
+        {
+            return Some(id);
+        }
+        self.start.get(bytes).copied()
+    }
+
     #[inline(always)]
     fn model(&self) -> Model {
         let mut vocab = self
@@ -142,11 +176,11 @@ impl WordPiece {
     /// Encodes the given parts into a sequence of tokens starting at individual characters.
     #[inline(never)]
     fn encode_chars(
-        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut Vec<TokenId>,
+        &self, parts: &[TextPart], fallback: &[Fallback], result: &mut impl EncodeOutput,
     ) -> Result<(), EncodeError> {
         for part in parts {
             if part.special != Token::INVALID {
-                result.push(part.special);
+                result.push_token(part.special, part.start, part.start + part.len());
                 continue;
             }
             self.encode_wordpiece(
@@ -154,24 +188,27 @@ impl WordPiece {
                 result,
                 part.char_indices().map(|(i, e, _)| (i, e)),
                 fallback.iter().copied().peekable(),
+                part.start,
             )?;
         }
         Ok(())
     }
 
     /// Encodes the given bytes into a sequence of tokens using the WordPiece algorithm.
+    ///
+    /// `offset` is the byte offset of the bytes in the normalized input.
     #[inline(never)]
     fn encode_wordpiece(
-        &self, bytes: &[u8], result: &mut Vec<TokenId>,
+        &self, bytes: &[u8], result: &mut impl EncodeOutput,
         indices: impl DoubleEndedIterator<Item = (usize, usize)> + Clone,
-        mut fallback: Peekable<impl Iterator<Item = Fallback>>,
+        mut fallback: Peekable<impl Iterator<Item = Fallback>>, offset: usize,
     ) -> Result<(), EncodeError> {
         if bytes.len() < self.min_token_bytes
             || self.max_word_chars > 0 && indices.clone().count() > self.max_word_chars
         {
             if fallback.peek() == Some(&Fallback::Unknown) && self.unknown.is_some() {
                 #[allow(clippy::unnecessary_unwrap)]
-                result.push(self.unknown.as_ref().unwrap().id);
+                result.push_token(self.unknown.as_ref().unwrap().id, offset, offset + bytes.len());
             } else if fallback.peek() == Some(&Fallback::Skip) {
             } else {
                 return Err(EncodeError::InvalidPiece(bytes[..].to_vec()));
@@ -186,6 +223,7 @@ impl WordPiece {
             &self.continuation,
             self.unknown.as_ref().map(|t| t.id),
             &mut fallback,
+            offset,
         )
     }
 
@@ -200,13 +238,14 @@ impl WordPiece {
             "wasm32+simd128",
         ))
     )]
+    #[allow(clippy::too_many_arguments)]
     fn merge_parts(
-        bytes: &[u8], result: &mut Vec<TokenId>,
+        bytes: &[u8], result: &mut impl EncodeOutput,
         mut indices: impl DoubleEndedIterator<Item = (usize, usize)> + Clone, starts: &VocabMap,
         continuations: &VocabMap, unknown: Option<TokenId>,
-        fallback: &mut Peekable<impl Iterator<Item = Fallback>>,
+        fallback: &mut Peekable<impl Iterator<Item = Fallback>>, offset: usize,
     ) -> Result<(), EncodeError> {
-        let init = result.len();
+        let init = result.token_count();
         let mut first = true;
         let mut until = 0;
         let stop = [(0, bytes.len())];
@@ -223,16 +262,18 @@ impl WordPiece {
                     continuations.get(&piece).copied()
                 };
                 if let Some(token) = token {
-                    result.push(token);
+                    result.push_token(token, offset + start, offset + end);
                     first = false;
                     until = end;
                     break;
                 }
             }
             if until <= start {
-                result.truncate(init);
-                if fallback.peek() == Some(&Fallback::Unknown) && unknown.is_some() {
-                    result.extend(unknown);
+                result.truncate_tokens(init);
+                if fallback.peek() == Some(&Fallback::Unknown)
+                    && let Some(unknown) = unknown
+                {
+                    result.push_token(unknown, offset, offset + bytes.len());
                 } else if fallback.peek() == Some(&Fallback::Skip) {
                 } else {
                     return Err(EncodeError::InvalidPiece(bytes[start..].to_vec()));
@@ -0,0 +1,365 @@
+//! Encoding with offsets.
+
+use alloc::vec::Vec;
+
+use crate::{
+    Alignment, EncodeError, Kitoken, ProcessingDirection, SpecialTokenKind, SpecialTokenKinds,
+    TokenId,
+};
+
+/// Sequence of tokens with the byte range of each token in the input text.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Encoding {
+    /// The tokens.
+    pub tokens:   Vec<TokenId>,
+    /// The `(start, end)` byte range of each token in the input text.
+    ///
+    /// Tokens inserted during processing, like padding tokens, have an empty range.
+    /// For pairs of sequences, the range refers to the input text of the sequence the token belongs to.
+    pub offsets:  Vec<(usize, usize)>,
+    /// The type id of each token.
+    ///
+    /// Tokens of single sequences have type id 0, tokens of pairs of sequences have the type ids of the configuration,
+    /// see [`TemplateTypeIds`](crate::TemplateTypeIds).
+    pub type_ids: Vec<u32>,
+}
+impl Encoding {
+    #[inline(always)]
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            tokens:   Vec::with_capacity(capacity),
+            offsets:  Vec::with_capacity(capacity),
+            type_ids: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Converts the encoding into a list of tokens with the given type id for processing.
+    #[inline(always)]
+    fn into_processed(self, type_id: u32) -> Vec<(TokenId, (usize, usize), u32)> {
+        let tokens = self.tokens.into_iter().zip(self.offsets);
+        tokens.map(|(token, offset)| (token, offset, type_id)).collect()
+    }
+
+    /// Creates an encoding from a list of processed tokens.
+    #[inline(always)]
+    fn from_processed(tokens: Vec<(TokenId, (usize, usize), u32)>) -> Self {
+        let mut encoding = Self::with_capacity(tokens.len());
+        for (token, offset, type_id) in tokens {
+            encoding.tokens.push(token);
+            encoding.offsets.push(offset);
+            encoding.type_ids.push(type_id);
+        }
+        encoding
+    }
+
+    /// Returns the number of tokens.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Returns `true` if the encoding contains no tokens.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+}
+
+impl Kitoken {
+    /// Encodes the given text into a sequence of tokens with the byte range of each token in the input text.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// Offsets of tokens in parts of the input unchanged by normalization are exact byte ranges, and may split characters for byte-level tokens.
+    /// Offsets of tokens in normalized parts of the input are mapped back to the input text on a per-character basis.
+    /// Characters inserted by normalization map to an empty range, and characters replaced by normalization map to the range of the replaced characters.
+    ///
+    /// Returns the encoding, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_with_offsets(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Encoding, EncodeError> {
+        let result =
+            self.inner_encode_offsets(text.as_ref(), encode_specials.as_kinds(&self.meta), None)?;
+        if self.config.processing.is_empty() {
+            return Ok(result);
+        }
+        let mut tokens = result.into_processed(0);
+        self.config.process_tokens(&mut tokens, 0, |_| {});
+        Ok(Encoding::from_processed(tokens))
+    }
+
+    /// Encodes the given pair of texts into a sequence of tokens with the byte range of each token in the respective input text.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// The special tokens of the templates are inserted around the sequences, like `add_special_tokens` in HuggingFace Tokenizers,
+    /// resulting in the layout `[SequenceStart] [SubSequenceStart] first [SubSequenceEnd] [SequenceContinuation] [SubSequenceStart] second [SubSequenceEnd] [SequenceEnd]`
+    /// for the respective [`InsertionPosition`](crate::InsertionPosition) templates, for example `[CLS] first [SEP] second [SEP]` for BERT models.
+    /// The tokens get the type ids of the [`TemplateTypeIds`](crate::TemplateTypeIds) of the configuration,
+    /// by default type id 1 for the second sequence and the templates following it.
+    /// Truncation removes tokens from the longer sequence first and accounts for the inserted tokens, padding is applied to the combined sequence.
+    ///
+    /// Returns the encoding, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_pair(
+        &self, first: impl AsRef<str>, second: impl AsRef<str>,
+        encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Encoding, EncodeError> {
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let first = self.inner_encode_offsets(first.as_ref(), encode_specials, None)?;
+        let second = self.inner_encode_offsets(second.as_ref(), encode_specials, None)?;
+        let tokens = self.config.process_pair_tokens(
+            first.into_processed(self.templates.type_ids.first),
+            second.into_processed(self.templates.type_ids.second),
+            self.templates.pair_len(),
+            |first, second| self.templates.insert_pair(first, second),
+        );
+        Ok(Encoding::from_processed(tokens))
+    }
+
+    /// Finds the position to cut the given text at to fit it into a budget of tokens.
+    ///
+    /// `direction` specifies the side the text is truncated from, like in [`Processing::Truncate`](crate::Processing::Truncate).
+    /// [`ProcessingDirection::Right`] keeps the longest prefix `text[..index]`, [`ProcessingDirection::Left`] keeps the longest suffix `text[index..]`.
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// The cut is placed at a token boundary on a character boundary, such that the kept text encodes to at most `max_tokens` tokens
+    /// before post-tokenization processing. If the text fits into the budget, the cut is placed at the end or the start of the text respectively.
+    ///
+    /// Returns the byte index of the cut, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn truncate_text(
+        &self, text: impl AsRef<str>, max_tokens: usize, direction: ProcessingDirection,
+        encode_specials: impl SpecialTokenKinds,
+    ) -> Result<usize, EncodeError> {
+        let text = text.as_ref();
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let offsets = self.inner_encode_offsets(text, encode_specials, None)?.offsets;
+        if offsets.len() <= max_tokens {
+            return Ok(match direction {
+                ProcessingDirection::Left => 0,
+                ProcessingDirection::Right => text.len(),
+            });
+        }
+        let mut last = None;
+        match direction {
+            ProcessingDirection::Left => {
+                for (_, end) in offsets[..offsets.len() - max_tokens].iter().rev().copied() {
+                    let mut cut = end;
+                    while !text.is_char_boundary(cut) {
+                        cut += 1;
+                    }
+                    if last == Some(cut) {
+                        continue;
+                    }
+                    if self.inner_count(&text[cut..], encode_specials)? <= max_tokens {
+                        return Ok(cut);
+                    }
+                    last = Some(cut);
+                }
+                Ok(text.len())
+            }
+            ProcessingDirection::Right => {
+                for (start, _) in offsets[..=max_tokens].iter().rev().copied() {
+                    let mut cut = start;
+                    while !text.is_char_boundary(cut) {
+                        cut -= 1;
+                    }
+                    if last == Some(cut) {
+                        continue;
+                    }
+                    if self.inner_count(&text[..cut], encode_specials)? <= max_tokens {
+                        return Ok(cut);
+                    }
+                    last = Some(cut);
+                }
+                Ok(0)
+            }
+        }
+    }
+
+    /// Encodes the given text into a sequence of tokens with the byte range of each token in the input text, without post-tokenization processing.
+    ///
+    /// If `boundaries` is set, a flag for each token is pushed to it, which is `true` if the token starts a part of the pre-tokenization split.
+    #[inline(never)]
+    pub(crate) fn inner_encode_offsets(
+        &self, text: &str, encode_specials: &[SpecialTokenKind], boundaries: Option<&mut Vec<bool>>,
+    ) -> Result<Encoding, EncodeError> {
+        let mut alignments = Vec::new();
+        let chunks = self.extract_parts(text, encode_specials, Some(&mut alignments));
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let starts = boundaries
+            .as_ref()
+            .map(|_| parts.iter().map(|part| part.start).collect::<Vec<_>>());
+        let mut result = self.encoder.encode_offsets(text, &mut parts)?;
+        if let (Some(boundaries), Some(starts)) = (boundaries, starts) {
+            let mut starts = starts.into_iter().peekable();
+            for &(start, _) in &result.offsets {
+                while starts.next_if(|&part| part < start).is_some() {}
+                boundaries.push(starts.next_if_eq(&start).is_some());
+            }
+        }
+        for offset in result.offsets.iter_mut() {
+            *offset = map_offset(&alignments, *offset);
+        }
+        Ok(result)
+    }
+}
+
+/// Alignment between a chunk of the normalized input and the input text.
+#[derive(Debug)]
+pub(crate) struct ChunkAlignment {
+    /// Byte range of the chunk in the normalized input.
+    start:  usize,
+    end:    usize,
+    /// Byte range of the chunk in the input text.
+    source: (usize, usize),
+    /// Byte offset of each character in the normalized chunk with the byte range in the input text,
+    /// or empty if the chunk is unchanged by normalization.
+    chars:  Vec<(usize, usize, usize)>,
+}
+impl ChunkAlignment {
+    /// Creates the alignment of the chunk at byte offset `start` in the normalized input, normalized from `text[source.0..source.1]`.
+    #[inline(always)]
+    pub(crate) fn new(
+        text: &str, chunk: &str, start: usize, source: (usize, usize), alignment: Alignment,
+    ) -> Self {
+        let chars = if *chunk == text[source.0..source.1] {
+            Vec::with_capacity(0)
+        } else {
+            alignment.chars
+        };
+        Self {
+            start,
+            end: start + chunk.len(),
+            source,
+            chars,
+        }
+    }
+
+    /// Maps a byte offset in the normalized chunk to a byte range in the input text.
+    #[inline(always)]
+    fn map(&self, offset: usize) -> (usize, usize) {
+        let offset = offset - self.start;
+        if self.chars.is_empty() {
+            let offset = (self.source.0 + offset).min(self.source.1);
+            return (offset, offset);
+        }
+        let index = self.chars.partition_point(|&(s, _, _)| s <= offset).saturating_sub(1);
+        let (_, start, end) = self.chars[index];
+        (start, end)
+    }
+}
+
+/// Maps a byte range in the normalized input to a byte range in the input text.
+#[inline(always)]
+pub(crate) fn map_offset(
+    alignments: &[ChunkAlignment], (start, end): (usize, usize),
+) -> (usize, usize) {
+    let index = alignments.partition_point(|chunk| chunk.end <= start);
+    let Some(chunk) = alignments.get(index).or(alignments.last()) else {
+        return (0, 0);
+    };
+    let start = start.clamp(chunk.start, chunk.end);
+    let end = end.clamp(start, chunk.end);
+    if chunk.chars.is_empty() {
+        return (chunk.map(start).0, chunk.map(end).0);
+    }
+    if start == chunk.end {
+        return (chunk.source.1, chunk.source.1);
+    }
+    let (source_start, source_end) = chunk.map(start);
+    if end == start {
+        return (source_start, source_start);
+    }
+    (source_start, chunk.map(end - 1).1.max(source_end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::borrow::Cow;
+    use alloc::string::ToString;
+
+    use crate::{Configuration, Normalization, Regex};
+
+    fn align(source: &str, normalization: &[Normalization]) -> [ChunkAlignment; 1] {
+        let config = Configuration {
+            normalization: normalization.to_vec(),
+            ..Configuration::default()
+        };
+        let mut text = Cow::Borrowed(source);
+        let mut alignment = Alignment::new(source, 0);
+        config.normalize_aligned(&mut text, 0..usize::MAX, &mut alignment);
+        [ChunkAlignment::new(source, &text, 0, (0, source.len()), alignment)]
+    }
+
+    #[test]
+    fn test_alignment_identity() {
+        let alignments = align("hello world", &[Normalization::CaseFold { upper: false }]);
+        assert_eq!(map_offset(&alignments, (0, 5)), (0, 5));
+        assert_eq!(map_offset(&alignments, (6, 11)), (6, 11));
+    }
+
+    #[test]
+    fn test_alignment_replaced() {
+        let alignments = align("Hello World", &[
+            Normalization::CaseFold { upper: false },
+            Normalization::Replace {
+                pattern:     ' '.into(),
+                replacement: "▁".to_string(),
+            },
+            Normalization::Prepend {
+                prepend: "▁".to_string(),
+            },
+        ]);
+        assert_eq!(map_offset(&alignments, (0, 3)), (0, 0));
+        assert_eq!(map_offset(&alignments, (0, 8)), (0, 5));
+        assert_eq!(map_offset(&alignments, (8, 16)), (5, 11));
+    }
+
+    #[cfg(feature = "normalization-unicode")]
+    #[test]
+    fn test_alignment_expanded() {
+        use crate::UnicodeNormalization;
+        let normalization = [Normalization::Unicode {
+            scheme: UnicodeNormalization::NFKC,
+        }];
+        let alignments = align("ﬁne x", &normalization);
+        assert_eq!(map_offset(&alignments, (0, 1)), (0, 3));
+        assert_eq!(map_offset(&alignments, (0, 4)), (0, 5));
+        assert_eq!(map_offset(&alignments, (5, 6)), (6, 7));
+        let alignments = align("ｈｅｌｌｏ ㍿ e\u{301}x", &normalization);
+        assert_eq!(map_offset(&alignments, (0, 5)), (0, 15));
+        assert_eq!(map_offset(&alignments, (6, 18)), (16, 19));
+        assert_eq!(map_offset(&alignments, (19, 21)), (20, 23));
+        assert_eq!(map_offset(&alignments, (21, 22)), (23, 24));
+    }
+
+    #[test]
+    fn test_alignment_inserted() {
+        let alignments = align("a日本b", &[Normalization::Replace {
+            pattern:     Regex::new(r"([日本])").unwrap().into(),
+            replacement: " $1 ".to_string(),
+        }]);
+        assert_eq!(map_offset(&alignments, (2, 5)), (1, 4));
+        assert_eq!(map_offset(&alignments, (7, 10)), (4, 7));
+        assert_eq!(map_offset(&alignments, (11, 12)), (7, 8));
+    }
+
+    #[test]
+    fn test_alignment_long_replacement() {
+        let replacement = "<a replacement longer than thirty-two bytes>";
+        let alignments = align("a|b|c", &[Normalization::Replace {
+            pattern:     '|'.into(),
+            replacement: replacement.to_string(),
+        }]);
+        let len = replacement.len();
+        assert_eq!(map_offset(&alignments, (0, 1)), (0, 1));
+        assert_eq!(map_offset(&alignments, (1, 1 + len)), (1, 2));
+        assert_eq!(map_offset(&alignments, (1 + len, 2 + len)), (2, 3));
+        assert_eq!(map_offset(&alignments, (2 + len, 2 + len * 2)), (3, 4));
+        assert_eq!(map_offset(&alignments, (2 + len * 2, 3 + len * 2)), (4, 5));
+    }
+}
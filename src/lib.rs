@@ -15,7 +15,7 @@
 //!
 //! # Overview
 //!
-//! Kitoken is a fast and versatile tokenizer for language models with support for BPE, Unigram and WordPiece tokenization.
+//! Kitoken is a fast and versatile tokenizer for language models with support for BPE, Unigram, WordPiece and WordLevel tokenization.
 //!
 //! Kitoken is compatible with many existing tokenizer formats,
 //! including [SentencePiece](https://github.com/google/sentencepiece), [HuggingFace Tokenizers](https://github.com/huggingface/tokenizers), [OpenAI Tiktoken](https://github.com/openai/tiktoken) and [Mistral Tekken](https://docs.mistral.ai/guides/tokenization/),
@@ -27,7 +27,7 @@
 //!
 //! ### Default features
 //!
-//! - `std`: Enables standard library features, including reading and writing definitions from and to files.
+//! - `std`: Enables standard library features, including reading and writing definitions from and to files, and training definitions with [`train`].
 //! - `serialization`: Enables `serde` implementations and methods for serialization and deserialization of definitions.
 //! - `normalization`: Enables all input normalization features. When disabled, individual normalizers can be enabled using the following features:
 //!   - `normalization-unicode`: Enables unicode input normalization support. This is required for certain models.
@@ -51,6 +51,7 @@
 //! - `regex-unicode`: Enables support for additional regex unicode patterns including script and segmentation extensions.
 //!   Disabled by default since it increases binary size and the majority of models don't make use of these patterns.
 //! - `web`: Enables fetching definitions from HuggingFace or other URLs.
+//! - `parallel`: Enables parallel batch encoding using `rayon`.
 
 #![no_std]
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -61,21 +62,31 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+mod batch;
 mod charsmap;
+mod chunk;
 mod config;
 mod decoder;
 mod definition;
 mod encoder;
+mod encoding;
 mod regex;
+mod sampling;
+mod session;
 mod vocab;
 
+#[cfg(feature = "std")]
+mod reader;
 #[cfg(feature = "serialization")]
 mod serialization;
 #[cfg(feature = "web")]
 mod web;
 
 pub mod convert;
+#[cfg(feature = "std")]
+pub mod train;
 
+use alloc::borrow::Cow;
 use alloc::boxed::Box;
 use alloc::fmt::Debug;
 use alloc::string::String;
@@ -86,13 +97,19 @@ use derive_more::{Deref, DerefMut};
 use hashbrown::HashMap;
 
 pub use crate::charsmap::*;
+pub use crate::chunk::*;
 pub use crate::config::*;
 pub use crate::decoder::*;
 pub use crate::definition::*;
 pub use crate::encoder::*;
+pub use crate::encoding::*;
 pub use crate::regex::*;
+pub use crate::sampling::*;
+pub use crate::session::*;
 pub use crate::vocab::*;
 
+#[cfg(feature = "std")]
+pub use crate::reader::*;
 #[cfg(feature = "serialization")]
 pub use crate::serialization::*;
 #[cfg(feature = "web")]
@@ -121,6 +138,9 @@ pub enum InitializationError {
         "special encoder and decoder must have the same length and specials must not have duplicates"
     )]
     InvalidSpecialEncoder,
+    /// The special token must not collide with the id or bytes of an existing token.
+    #[error("special token {0} collides with an existing token")]
+    SpecialTokenCollision(TokenId),
     /// The split regex failed to compile.
     #[error("invalid regex: {0}")]
     InvalidRegex(String),
@@ -162,6 +182,104 @@ impl Debug for SpecialsMap {
     }
 }
 
+/// Template tokens resolved against the special vocabulary.
+#[derive(Debug, Clone, Default)]
+struct TemplateTokens {
+    sequence_start:        Vec<TokenId>,
+    subsequence_start:     Vec<TokenId>,
+    subsequence_end:       Vec<TokenId>,
+    sequence_continuation: Vec<TokenId>,
+    sequence_end:          Vec<TokenId>,
+    type_ids:              TemplateTypeIds,
+}
+impl TemplateTokens {
+    /// Resolves the sequence templates against the special vocabulary.
+    ///
+    /// Templates are resolved by the bytes of the special token, or by the identifier if no special token with the same bytes exists.
+    /// Both have to match the content of the template exactly. Templates without a matching special token are skipped.
+    #[inline(never)]
+    fn new(templates: &[Template], type_ids: TemplateTypeIds, specials: &SpecialsMap) -> Self {
+        let mut tokens = Self {
+            type_ids,
+            ..Self::default()
+        };
+        for template in templates {
+            let target = match template.position {
+                InsertionPosition::SequenceStart => &mut tokens.sequence_start,
+                InsertionPosition::SubSequenceStart => &mut tokens.subsequence_start,
+                InsertionPosition::SubSequenceEnd => &mut tokens.subsequence_end,
+                InsertionPosition::SequenceContinuation => &mut tokens.sequence_continuation,
+                InsertionPosition::SequenceEnd => &mut tokens.sequence_end,
+                _ => continue,
+            };
+            let special = specials.get(template.content.as_bytes()).or_else(|| {
+                let ident = Some(template.content.as_str());
+                specials.values().find(|special| special.ident.as_deref() == ident)
+            });
+            if let Some(special) = special {
+                target.push(special.id);
+            } else {
+                log::warn!("Template {:?} has no matching special token", template.content);
+            }
+        }
+        tokens
+    }
+
+    /// Returns the number of tokens inserted around a single sequence.
+    #[inline(always)]
+    fn single_len(&self) -> usize {
+        self.sequence_start.len()
+            + self.subsequence_start.len()
+            + self.subsequence_end.len()
+            + self.sequence_end.len()
+    }
+
+    /// Returns the number of tokens inserted around a pair of sequences.
+    #[inline(always)]
+    fn pair_len(&self) -> usize {
+        self.single_len()
+            + self.subsequence_start.len()
+            + self.subsequence_end.len()
+            + self.sequence_continuation.len()
+    }
+
+    /// Inserts the template tokens around a pair of sequences, assigning the template type ids to the inserted tokens.
+    #[inline(never)]
+    fn insert_pair(
+        &self, first: Vec<(TokenId, (usize, usize), u32)>,
+        second: Vec<(TokenId, (usize, usize), u32)>,
+    ) -> Vec<(TokenId, (usize, usize), u32)> {
+        let TemplateTypeIds {
+            sequence_start,
+            first: first_type,
+            sequence_continuation,
+            second: second_type,
+            sequence_end,
+        } = self.type_ids;
+        let inserted = |type_id: u32| move |&id: &TokenId| (id, (0, 0), type_id);
+        let mut tokens = Vec::with_capacity(first.len() + second.len() + self.pair_len());
+        tokens.extend(self.sequence_start.iter().map(inserted(sequence_start)));
+        tokens.extend(self.subsequence_start.iter().map(inserted(first_type)));
+        tokens.extend(first);
+        tokens.extend(self.subsequence_end.iter().map(inserted(first_type)));
+        tokens.extend(self.sequence_continuation.iter().map(inserted(sequence_continuation)));
+        tokens.extend(self.subsequence_start.iter().map(inserted(second_type)));
+        tokens.extend(second);
+        tokens.extend(self.subsequence_end.iter().map(inserted(second_type)));
+        tokens.extend(self.sequence_end.iter().map(inserted(sequence_end)));
+        tokens
+    }
+
+    /// Inserts the template tokens around a single sequence.
+    #[inline(never)]
+    fn insert_single<T: ProcessedToken>(&self, tokens: &mut Vec<T>) {
+        let start = self.sequence_start.iter().chain(&self.subsequence_start);
+        tokens.splice(0..0, start.map(|&id| T::inserted(id)));
+        let end = self.subsequence_end.iter().chain(&self.sequence_end);
+        tokens.extend(end.map(|&id| T::inserted(id)));
+    }
+}
+
 /// Special token specification for encoding and decoding.
 pub trait SpecialTokenKinds {
     fn as_kinds(&self, meta: &Metadata) -> &[SpecialTokenKind];
@@ -203,7 +321,8 @@ pub struct Kitoken {
     encoder: Box<dyn Encoder>,
     decoder: Decoder,
 
-    specials: SpecialsMap,
+    specials:  SpecialsMap,
+    templates: TemplateTokens,
 
     extract_split: Regex,
     special_split: Regex,
@@ -215,7 +334,7 @@ impl Kitoken {
     /// Creates a tokenizer from the given encoder, specials, scores and config.
     ///
     /// Returns an error if the config is invalid, the special encoder contains invalid utf-8, the encoder or special encoder contain duplicates,
-    /// or the encoder and scores have different lengths in unigram mode.
+    /// the encoder and scores have different lengths in unigram mode, or a special token has the id of a byte or character of a model without a stored vocabulary.
     #[inline(never)]
     pub fn new(
         model: Model, specials: SpecialVocab, config: Configuration, meta: Metadata,
@@ -224,28 +343,19 @@ impl Kitoken {
             return Err(InitializationError::InvalidConfig(error));
         }
 
-        let special_split = Regex::new(
-            &specials
-                .iter()
-                .filter(|special| !special.extract)
-                .map(|special| core::str::from_utf8(&special.bytes))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .map(|s| regex::escape(s))
-                .collect::<Vec<_>>()
-                .join("|"),
-        )?;
-        let extract_split = Regex::new(
-            &specials
-                .iter()
-                .filter(|special| special.extract)
-                .map(|special| core::str::from_utf8(&special.bytes))
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .map(|s| regex::escape(s))
-                .collect::<Vec<_>>()
-                .join("|"),
-        )?;
+        let special_split = Self::special_regex(&specials, false)?;
+        let extract_split = Self::special_regex(&specials, true)?;
+
+        let implicit = match model {
+            Model::Bytes { offset } => Some(ImplicitVocab::Bytes { offset }),
+            Model::Chars => Some(ImplicitVocab::Chars),
+            _ => None,
+        };
+        if let Some(implicit) = implicit
+            && let Some(special) = specials.iter().find(|special| implicit.contains(special.id))
+        {
+            return Err(InitializationError::SpecialTokenCollision(special.id));
+        }
 
         let (encoder, decoder) = match model {
             Model::BytePair { vocab, chars } => {
@@ -267,6 +377,22 @@ impl Kitoken {
                     Box::new(WordPiece::new(vocab, &specials, &config, max_word_chars)) as _;
                 (encoder, decoder)
             }
+            Model::WordLevel { vocab } => {
+                let decoder = Decoder::new(&vocab, &specials, &config);
+                let encoder = Box::new(WordLevel::new(vocab, &specials, &config)) as _;
+                (encoder, decoder)
+            }
+            Model::Bytes { offset } => {
+                let implicit = ImplicitVocab::Bytes { offset };
+                let decoder = Decoder::new_implicit(implicit, &specials, &config);
+                let encoder = Box::new(Bytes::new(offset)?) as _;
+                (encoder, decoder)
+            }
+            Model::Chars => {
+                let decoder = Decoder::new_implicit(ImplicitVocab::Chars, &specials, &config);
+                let encoder = Box::new(Chars) as _;
+                (encoder, decoder)
+            }
         };
 
         let specials_len = specials.len();
@@ -277,11 +403,13 @@ impl Kitoken {
         if specials_len != specials.len() {
             return Err(InitializationError::InvalidSpecialEncoder);
         }
+        let templates = TemplateTokens::new(&config.templates, config.type_ids, &specials);
 
         Ok(Self {
             encoder,
             decoder,
             specials,
+            templates,
             special_split,
             extract_split,
             config,
@@ -289,6 +417,24 @@ impl Kitoken {
         })
     }
 
+    /// Builds the split regex for the special tokens with the given `extract` setting, in order of the given tokens.
+    #[inline(never)]
+    fn special_regex<'a>(
+        specials: impl IntoIterator<Item = &'a SpecialToken>, extract: bool,
+    ) -> Result<Regex, InitializationError> {
+        Ok(Regex::new(
+            &specials
+                .into_iter()
+                .filter(|special| special.extract == extract)
+                .map(|special| core::str::from_utf8(&special.bytes))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .map(|s| regex::escape(s))
+                .collect::<Vec<_>>()
+                .join("|"),
+        )?)
+    }
+
     /// Encodes the given text into a sequence of tokens.
     ///
     /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
@@ -298,28 +444,118 @@ impl Kitoken {
     pub fn encode(
         &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
     ) -> Result<Vec<TokenId>, EncodeError> {
-        self.inner_encode(text, encode_specials.as_kinds(&self.meta))
+        self.inner_encode(text, encode_specials.as_kinds(&self.meta), false)
+    }
+
+    /// Encodes the given text into a sequence of tokens and inserts the special tokens of the sequence templates.
+    ///
+    /// Works like [`encode`](Self::encode), and additionally inserts the special tokens of the [`InsertionPosition::SequenceStart`], [`InsertionPosition::SubSequenceStart`],
+    /// [`InsertionPosition::SubSequenceEnd`] and [`InsertionPosition::SequenceEnd`] templates around the tokens, like `add_special_tokens` in HuggingFace Tokenizers.
+    /// The inserted tokens are accounted for during truncation and included in padding.
+    ///
+    /// Returns a list of tokens, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(always)]
+    pub fn encode_with_templates(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Vec<TokenId>, EncodeError> {
+        self.inner_encode(text, encode_specials.as_kinds(&self.meta), true)
+    }
+
+    /// Counts the tokens the given text is encoded into.
+    ///
+    /// Works like [`encode`](Self::encode), but counts the tokens without allocating the list of tokens.
+    /// The intermediate parts and the scratch buffers of the encoder are still allocated for each call.
+    /// When the configuration contains post-tokenization processing, the tokens are encoded and processed before counting.
+    ///
+    /// Returns the number of tokens, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn count_tokens(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+    ) -> Result<usize, EncodeError> {
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        if !self.config.processing.is_empty() {
+            return self.inner_encode(text, encode_specials, false).map(|tokens| tokens.len());
+        }
+        self.inner_count(text.as_ref(), encode_specials)
+    }
+
+    /// Counts the tokens the given text is encoded into before post-tokenization processing.
+    #[inline(never)]
+    fn inner_count(
+        &self, text: &str, encode_specials: &[SpecialTokenKind],
+    ) -> Result<usize, EncodeError> {
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        self.encoder.count(text, &mut parts)
+    }
+
+    #[inline(never)]
+    fn inner_encode(
+        &self, text: impl AsRef<str>, encode_specials: &[SpecialTokenKind], templates: bool,
+    ) -> Result<Vec<TokenId>, EncodeError> {
+        let text = text.as_ref();
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let mut result = self.encoder.encode(text, &mut parts)?;
+        if templates {
+            self.config.process_tokens(&mut result, self.templates.single_len(), |tokens| {
+                self.templates.insert_single(tokens)
+            });
+        } else {
+            self.config.process(&mut result);
+        }
+        Ok(result)
     }
 
-    /// Normalizes `text[start..end]` and pushes it as a non-special part.
+    /// Normalizes `text[source.0..source.1]` and pushes it as a non-special part.
+    ///
+    /// `offset` is the byte offset of the text in the input, and `last` is `true` if the text ends the input.
+    /// If `alignments` is set, the alignment of the part with the text is pushed to it.
     #[inline(always)]
     fn push_normalized<'a>(
-        &self, parts: &mut Vec<TextPart<'a>>, text: &'a str, start: usize, end: usize,
+        &self, parts: &mut Vec<TextPart<'a>>, text: &'a str, source: (usize, usize),
+        offset: usize, last: bool, alignments: Option<&mut Vec<ChunkAlignment>>,
     ) {
+        let (start, end) = source;
         let mut part = text[start..end].into();
-        let position_end = if end == text.len() { usize::MAX } else { end };
-        self.config.normalize(&mut part, start..position_end);
+        let position_end = if last && end == text.len() { usize::MAX } else { offset + end };
+        let part_start = parts.last().map(|last| last.start + last.len()).unwrap_or(0);
+        if let Some(alignments) = alignments {
+            let mut alignment = Alignment::new(&text[start..end], start);
+            self.config.normalize_aligned(&mut part, offset + start..position_end, &mut alignment);
+            alignments.push(ChunkAlignment::new(text, &part, part_start, source, alignment));
+        } else {
+            self.config.normalize(&mut part, offset + start..position_end);
+        }
         parts.push(TextPart {
             text:    part,
             special: Token::INVALID,
+            start:   part_start,
         });
     }
 
+    /// Extracts the special tokens marked for extraction from the text and normalizes the remaining chunks.
+    ///
+    /// Part offsets are relative to the normalized input. If `alignments` is set, the alignment of each part with the text is pushed to it.
+    #[inline(always)]
+    fn extract_parts<'a>(
+        &self, text: &'a str, encode_specials: &[SpecialTokenKind],
+        alignments: Option<&mut Vec<ChunkAlignment>>,
+    ) -> Vec<TextPart<'a>> {
+        let mut parts = Vec::new();
+        self.extract_parts_into(text, encode_specials, 0, true, alignments, &mut parts);
+        parts
+    }
+
+    /// Extracts the special tokens marked for extraction from the text and normalizes the remaining chunks into `parts`.
+    ///
+    /// `offset` is the byte offset of the text in the input, and `last` is `true` if the text ends the input.
+    /// See [`extract_parts`](Self::extract_parts).
     #[inline(never)]
-    fn inner_encode(
-        &self, text: impl AsRef<str>, encode_specials: &[SpecialTokenKind],
-    ) -> Result<Vec<TokenId>, EncodeError> {
-        let text = text.as_ref();
+    fn extract_parts_into<'a>(
+        &self, text: &'a str, encode_specials: &[SpecialTokenKind], offset: usize, last: bool,
+        mut alignments: Option<&mut Vec<ChunkAlignment>>, parts: &mut Vec<TextPart<'a>>,
+    ) {
         let mut extracted = if self.extract_split.is_empty() {
             Vec::with_capacity(0)
         } else {
@@ -327,32 +563,65 @@ impl Kitoken {
             extracted.reverse();
             extracted
         };
-        let mut parts = Vec::with_capacity(extracted.len() * 2 + 1);
+        parts.reserve(extracted.len() * 2 + 1);
         let mut posit = 0;
         while posit < text.len() {
             if let Some(next) = extracted.pop() {
                 if next.0 > posit {
-                    self.push_normalized(&mut parts, text, posit, next.0);
+                    let alignments = alignments.as_deref_mut();
+                    self.push_normalized(parts, text, (posit, next.0), offset, last, alignments);
                 }
                 let special = &self.specials[&text.as_bytes()[next.0..next.1]];
                 if encode_specials.contains(&special.kind) {
+                    let start = parts.last().map(|last| last.start + last.len()).unwrap_or(0);
+                    if let Some(alignments) = &mut alignments {
+                        let part = &text[next.0..next.1];
+                        let alignment = Alignment::default();
+                        alignments.push(ChunkAlignment::new(text, part, start, next, alignment));
+                    }
                     parts.push(TextPart {
-                        text:    text[next.0..next.1].into(),
+                        text: text[next.0..next.1].into(),
                         special: special.id,
+                        start,
                     });
                 } else {
-                    self.push_normalized(&mut parts, text, next.0, next.1);
+                    let alignments = alignments.as_deref_mut();
+                    self.push_normalized(parts, text, next, offset, last, alignments);
                 }
                 posit = next.1;
             } else {
-                self.push_normalized(&mut parts, text, posit, text.len());
+                let alignments = alignments.as_deref_mut();
+                self.push_normalized(parts, text, (posit, text.len()), offset, last, alignments);
                 posit = text.len();
             }
         }
-        let mut parts = parts.iter().fold(Vec::with_capacity(text.len() / 6), |mut acc, part| {
+    }
+
+    /// Splits the chunks into parts at non-extracted special tokens and according to the split configuration.
+    #[inline(always)]
+    fn split_parts<'a>(
+        &self, chunks: &'a [TextPart], encode_specials: &[SpecialTokenKind],
+    ) -> Vec<TextPart<'a>> {
+        let len = chunks.last().map(|last| last.start + last.len()).unwrap_or(0);
+        let mut parts = Vec::with_capacity(len / 6);
+        self.split_parts_into(chunks, encode_specials, &mut parts);
+        parts
+    }
+
+    /// Splits the chunks into `parts` at non-extracted special tokens and according to the split configuration.
+    #[inline(never)]
+    fn split_parts_into<'a>(
+        &self, chunks: &'a [TextPart], encode_specials: &[SpecialTokenKind],
+        parts: &mut Vec<TextPart<'a>>,
+    ) {
+        for part in chunks {
             let mut specials = if part.special != Token::INVALID {
-                acc.push(part.clone());
-                return acc;
+                parts.push(TextPart {
+                    text:    part.text.as_ref().into(),
+                    special: part.special,
+                    start:   part.start,
+                });
+                continue;
             } else if self.special_split.is_empty() {
                 Vec::with_capacity(0)
             } else {
@@ -374,35 +643,34 @@ impl Kitoken {
                     if next.0 > posit {
                         for (start, end) in self.config.split(&part.text[posit..next.0]) {
                             if end > start {
-                                acc.push(TextPart {
+                                parts.push(TextPart {
                                     text:    part.text[posit + start..posit + end].into(),
                                     special: Token::INVALID,
+                                    start:   part.start + posit + start,
                                 });
                             }
                         }
                     }
-                    acc.push(TextPart {
+                    parts.push(TextPart {
                         text:    part.text[next.0..next.1].into(),
                         special: next.2.id,
+                        start:   part.start + next.0,
                     });
                     posit = next.1;
                 } else {
                     for (start, end) in self.config.split(&part.text[posit..part.text.len()]) {
                         if end > start {
-                            acc.push(TextPart {
+                            parts.push(TextPart {
                                 text:    part.text[posit + start..posit + end].into(),
                                 special: Token::INVALID,
+                                start:   part.start + posit + start,
                             });
                         }
                     }
                     posit = part.text.len();
                 }
             }
-            acc
-        });
-        let mut result = self.encoder.encode(text, &mut parts)?;
-        self.config.process(&mut result);
-        Ok(result)
+        }
     }
 
     /// Decodes the given sequence of tokens into text.
@@ -410,16 +678,144 @@ impl Kitoken {
     /// `decode_specials` specifies which tokens from the special vocabulary are included in the output.
     ///
     /// Returns a list of bytes, or an error if no byte sequence for a token exists.
-    #[inline(never)]
+    #[inline(always)]
     pub fn decode(
         &self, tokens: impl AsRef<[TokenId]>, decode_specials: impl SpecialTokenKinds,
+    ) -> Result<Vec<u8>, DecodeError> {
+        self.decode_with_options(tokens, decode_specials, &DecodeOptions::default())
+    }
+
+    /// Decodes the given sequence of tokens into text with the given options.
+    ///
+    /// `decode_specials` specifies which tokens from the special vocabulary are included in the output.
+    /// `options` specifies how token ids not in the vocabulary or the special vocabulary are handled, see [`DecodeOptions`].
+    ///
+    /// Returns a list of bytes, or an error if no byte sequence for a token exists and unknown tokens are not allowed.
+    #[inline(never)]
+    pub fn decode_with_options(
+        &self, tokens: impl AsRef<[TokenId]>, decode_specials: impl SpecialTokenKinds,
+        options: &DecodeOptions,
     ) -> Result<Vec<u8>, DecodeError> {
         let tokens = tokens.as_ref();
-        let mut result = self.decoder.decode(tokens, decode_specials.as_kinds(&self.meta))?;
+        let mut result =
+            self.decoder.decode(tokens, decode_specials.as_kinds(&self.meta), options)?;
         self.config.decode(&mut result);
         Ok(result)
     }
 
+    /// Decodes the given sequence of tokens into a string with the given options.
+    ///
+    /// `decode_specials` specifies which tokens from the special vocabulary are included in the output.
+    /// `options` specifies how token ids not in the vocabulary or the special vocabulary are handled, see [`DecodeOptions`].
+    /// Invalid UTF-8 sequences in the decoded bytes, for example from a sequence ending inside a multi-byte character,
+    /// are replaced with the replacement character `U+FFFD`.
+    ///
+    /// Returns the text, or an error if no byte sequence for a token exists and unknown tokens are not allowed.
+    #[inline(never)]
+    pub fn decode_str(
+        &self, tokens: impl AsRef<[TokenId]>, decode_specials: impl SpecialTokenKinds,
+        options: &DecodeOptions,
+    ) -> Result<String, DecodeError> {
+        let result = self.decode_with_options(tokens, decode_specials, options)?;
+        Ok(String::from_utf8(result)
+            .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned()))
+    }
+
+    /// Decodes each token of the given sequence individually, for example for visualizing the tokenization.
+    ///
+    /// `decode_specials` specifies which tokens from the special vocabulary are included in the output.
+    ///
+    /// The range of each piece refers to the output of [`decode`](Self::decode) for the same tokens.
+    /// With a word continuation template, tokens without the subword prefix start a word. Otherwise, tokens starting with whitespace
+    /// and tokens following whitespace or a special token start a word. Special tokens and the first token always start a word.
+    ///
+    /// Returns a list of pieces, or an error if no byte sequence for a token exists.
+    #[inline(never)]
+    pub fn decode_pieces(
+        &self, tokens: impl AsRef<[TokenId]>, decode_specials: impl SpecialTokenKinds,
+    ) -> Result<Vec<Piece>, DecodeError> {
+        let tokens = tokens.as_ref();
+        let mut ranges = Vec::with_capacity(tokens.len());
+        let decode_specials = decode_specials.as_kinds(&self.meta);
+        let mut result = self.decoder.decode_ranges(tokens, decode_specials, &mut ranges)?;
+        self.config.decode_ranges(&mut result, &mut ranges);
+        let prefix = self.decoder.subword_prefix();
+        let mut pieces = Vec::<Piece>::with_capacity(tokens.len());
+        for (&id, range) in tokens.iter().zip(ranges) {
+            let bytes = self.decoder.token_bytes(id).unwrap_or_default().into_owned();
+            let special = self.decoder.decodes_special(id);
+            let mut text = bytes.clone();
+            self.config.decode_token(&mut text);
+            let text = String::from_utf8(text)
+                .unwrap_or_else(|error| String::from_utf8_lossy(error.as_bytes()).into_owned());
+            let word_start = match (pieces.last(), prefix) {
+                _ if special => true,
+                (None, _) => true,
+                (_, Some(prefix)) => !bytes.starts_with(prefix.as_bytes()),
+                (Some(last), None) => {
+                    last.special
+                        || last.text.ends_with(char::is_whitespace)
+                        || text.starts_with(char::is_whitespace)
+                }
+            };
+            pieces.push(Piece {
+                id,
+                bytes,
+                text,
+                special,
+                word_start,
+                range,
+            });
+        }
+        Ok(pieces)
+    }
+
+    /// Creates an incremental decoder for decoding a sequence of tokens one token at a time.
+    ///
+    /// `decode_specials` specifies which tokens from the special vocabulary are included in the output.
+    ///
+    /// See [`DecodeStream`] for details.
+    #[inline(always)]
+    pub fn decode_stream(&self, decode_specials: impl SpecialTokenKinds) -> DecodeStream<'_> {
+        DecodeStream::new(self, decode_specials)
+    }
+
+    /// Returns the log-probability of the given sequence of tokens, the sum of the scores of its tokens.
+    ///
+    /// Unknown tokens are scored like unknown characters during encoding, other special tokens are scored with `0.0`.
+    /// The score of the segmentations returned by [`encode_nbest`](Self::encode_nbest) equals their log-probability.
+    /// Only unigram models score tokens.
+    ///
+    /// Returns the log-probability, or an error if the model is not a unigram model, or if a token does not exist.
+    #[inline(never)]
+    pub fn score(&self, tokens: impl AsRef<[TokenId]>) -> Result<f64, EncodeError> {
+        let encoder = self.encoder.as_scored().ok_or(EncodeError::UnsupportedModel)?;
+        let mut result = 0.0;
+        for &token in tokens.as_ref() {
+            let bytes = self.decoder.token_bytes(token).ok_or(EncodeError::InvalidToken(token))?;
+            match encoder.token_score(token, &bytes) {
+                Some(score) => result += score,
+                None if self.decoder.is_special(token) => {}
+                None => return Err(EncodeError::InvalidToken(token)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns `true` if the given sequence of tokens is the canonical encoding of the given text.
+    ///
+    /// The canonical encoding is the sequence returned by [`encode`](Self::encode), for byte pair models the result of applying all merges in order of priority.
+    /// Other sequences decoding to the same text, like the tokens produced by generation models or [`encode_dropout`](Self::encode_dropout), are not canonical.
+    ///
+    /// Returns the result, or an error if the text can not be encoded.
+    #[inline(never)]
+    pub fn is_canonical(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+        tokens: impl AsRef<[TokenId]>,
+    ) -> Result<bool, EncodeError> {
+        Ok(self.encode(text, encode_specials)? == tokens.as_ref())
+    }
+
     /// Returns the configuration.
     #[inline(always)]
     pub fn config(&self) -> &Configuration {
@@ -431,4 +827,122 @@ impl Kitoken {
     pub fn meta(&self) -> &Metadata {
         &self.meta
     }
+
+    /// Returns the id of the token with the given bytes, looking up the vocabulary first and the special vocabulary second.
+    ///
+    /// Returns `None` if no token with the given bytes exists.
+    #[inline(never)]
+    pub fn token_to_id(&self, bytes: impl AsRef<[u8]>) -> Option<TokenId> {
+        let bytes = bytes.as_ref();
+        self.encoder
+            .token_id(bytes)
+            .or_else(|| self.specials.get(bytes).map(|special| special.id))
+    }
+
+    /// Returns the bytes of the token with the given id from the vocabulary or the special vocabulary.
+    ///
+    /// Returns `None` if no token with the given id exists.
+    #[inline(always)]
+    pub fn id_to_token(&self, id: TokenId) -> Option<Cow<'_, [u8]>> {
+        self.decoder.token_bytes(id)
+    }
+
+    /// Returns the number of distinct token ids in the vocabulary and the special vocabulary.
+    #[inline(always)]
+    pub fn vocab_size(&self) -> usize {
+        self.decoder.len()
+    }
+
+    /// Returns `true` if the token with the given id is in the special vocabulary.
+    #[inline(always)]
+    pub fn is_special(&self, id: TokenId) -> bool {
+        self.decoder.is_special(id)
+    }
+
+    /// Returns the special token with the given identifier, for example `"unk"`, `"bos"`, `"eos"` or `"pad"`.
+    ///
+    /// See [`SpecialToken::ident`] for the identifiers set by the converters.
+    /// Returns `None` if no special token with the given identifier exists.
+    #[inline(never)]
+    pub fn special_by_ident(&self, ident: &str) -> Option<&SpecialToken> {
+        self.specials
+            .values()
+            .filter(|special| special.ident.as_deref() == Some(ident))
+            .min_by_key(|special| special.id)
+    }
+
+    /// Adds the given tokens to the special vocabulary.
+    ///
+    /// Only the special split regexes, the template tokens and the special decoder vocabulary are rebuilt, the encoder is left unchanged.
+    /// Added tokens of kind [`SpecialTokenKind::Unknown`] are not used as unknown token for the encoder fallback.
+    ///
+    /// Returns an error if the id or bytes of a token collide with an existing token, or if a token contains invalid utf-8.
+    /// The tokenizer is left unchanged on error.
+    #[inline(never)]
+    pub fn add_special_tokens(
+        &mut self, specials: impl IntoIterator<Item = SpecialToken>,
+    ) -> Result<(), InitializationError> {
+        let mut map = self.specials.clone();
+        let mut added = Vec::new();
+        for special in specials {
+            if self.decoder.token_bytes(special.id).is_some()
+                || added.iter().any(|added: &SpecialToken| added.id == special.id)
+                || self.encoder.token_id(&special.bytes).is_some()
+                || map.contains_key(&special.bytes)
+            {
+                return Err(InitializationError::SpecialTokenCollision(special.id));
+            }
+            map.insert(special.bytes.clone(), special.clone());
+            added.push(special);
+        }
+        self.update_specials(map)?;
+        for special in added {
+            self.decoder.insert_special(special);
+        }
+        Ok(())
+    }
+
+    /// Removes the tokens with the given ids from the special vocabulary.
+    ///
+    /// Only the special split regexes, the template tokens and the special decoder vocabulary are rebuilt, the encoder is left unchanged.
+    /// Ids without a token in the special vocabulary are ignored.
+    ///
+    /// Returns the removed tokens.
+    #[inline(never)]
+    pub fn remove_special_tokens(
+        &mut self, ids: impl IntoIterator<Item = TokenId>,
+    ) -> Result<Vec<SpecialToken>, InitializationError> {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let mut map = self.specials.clone();
+        let mut removed = Vec::new();
+        map.retain(|_, special| {
+            if ids.contains(&special.id) {
+                removed.push(special.clone());
+                false
+            } else {
+                true
+            }
+        });
+        self.update_specials(map)?;
+        for special in &removed {
+            self.decoder.remove_special(special.id);
+        }
+        removed.sort();
+        Ok(removed)
+    }
+
+    /// Replaces the special vocabulary and rebuilds the special split regexes and template tokens.
+    #[inline(never)]
+    fn update_specials(&mut self, specials: SpecialsMap) -> Result<(), InitializationError> {
+        let mut sorted = specials.values().collect::<Vec<_>>();
+        sorted.sort();
+        let special_split = Self::special_regex(sorted.iter().copied(), false)?;
+        let extract_split = Self::special_regex(sorted, true)?;
+        self.templates =
+            TemplateTokens::new(&self.config.templates, self.config.type_ids, &specials);
+        self.special_split = special_split;
+        self.extract_split = extract_split;
+        self.specials = specials;
+        Ok(())
+    }
 }
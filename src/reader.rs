@@ -0,0 +1,273 @@
+//! Streaming encoding from readers.
+
+use std::io::{ErrorKind, Read};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::encoding::map_offset;
+use crate::{EncodeError, Kitoken, SpecialTokenKind, SpecialTokenKinds, TextPart, TokenId};
+
+/// Errors encountered when encoding from a reader.
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum EncodeReaderError {
+    /// The input could not be read.
+    #[error("{0}")]
+    IOError(#[from] std::io::Error),
+    /// The input contains invalid utf-8 at the given byte offset.
+    #[error("invalid utf-8 at byte {0}")]
+    InvalidUtf8(usize),
+    /// No position to cut the input at was found within the given maximum number of buffered bytes.
+    #[error("no cut found within {0} buffered bytes")]
+    BufferLimit(usize),
+    /// The input could not be encoded.
+    #[error("{0}")]
+    EncodeError(#[from] EncodeError),
+}
+
+/// Iterator over the tokens of a text read from a reader.
+///
+/// Created with [`Kitoken::encode_reader`]. Reads the input in blocks of `buffer_size` bytes and yields the tokens of the input in chunks.
+/// Incomplete utf-8 sequences and the unfinished trailing parts of the pre-tokenization split are carried over to the next block,
+/// and the input is only cut where normalizing and splitting both sides separately produces the same parts as the whole block.
+/// If no such position exists in a block, for example when the configuration has no split or normalizes every chunk as a whole,
+/// the next block is appended and the input is buffered until a cut is found.
+///
+/// The buffered text is limited to `max_buffered` bytes, [`DEFAULT_MAX_BUFFERED`](Self::DEFAULT_MAX_BUFFERED) by default.
+/// When the limit is reached without finding a cut, iteration fails with [`EncodeReaderError::BufferLimit`] instead of cutting the input
+/// at a position that changes the tokens. Use [`with_max_buffered`](Self::with_max_buffered) with `usize::MAX` to buffer the whole input if needed.
+/// The concatenated chunks are equal to the tokens returned by [`Kitoken::encode`] for the whole input without post-tokenization processing.
+/// Iteration stops after the first error.
+#[derive(Debug)]
+pub struct EncodeReader<'a, R> {
+    tokenizer: &'a Kitoken,
+    reader:    R,
+    specials:  Vec<SpecialTokenKind>,
+
+    buffer_size:  usize,
+    max_buffered: usize,
+    lookahead:    usize,
+
+    bytes:    Vec<u8>,
+    pending:  String,
+    position: usize,
+    attempt:  usize,
+    finished: bool,
+}
+impl<'a, R: Read> EncodeReader<'a, R> {
+    /// The default number of bytes read at once.
+    pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+    /// The default maximum number of bytes buffered while searching for a cut.
+    pub const DEFAULT_MAX_BUFFERED: usize = 4 * 1024 * 1024;
+    /// The maximum number of part boundaries tried for cutting a block.
+    const MAX_CUT_ATTEMPTS: usize = 8;
+
+    #[inline(always)]
+    pub(crate) fn new(
+        tokenizer: &'a Kitoken, reader: R, encode_specials: impl SpecialTokenKinds,
+    ) -> Self {
+        let specials = encode_specials.as_kinds(&tokenizer.meta).to_vec();
+        let lookahead = tokenizer.specials.keys().map(|bytes| bytes.len()).max().unwrap_or(0);
+        Self {
+            tokenizer,
+            reader,
+            specials,
+            buffer_size: Self::DEFAULT_BUFFER_SIZE,
+            max_buffered: Self::DEFAULT_MAX_BUFFERED,
+            lookahead,
+            bytes: Vec::new(),
+            pending: String::new(),
+            position: 0,
+            attempt: Self::DEFAULT_BUFFER_SIZE,
+            finished: false,
+        }
+    }
+
+    /// Sets the number of bytes read at once. Larger blocks allow more positions for cutting the input.
+    #[inline(always)]
+    pub fn with_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self.attempt = self.buffer_size;
+        self
+    }
+
+    /// Sets the maximum number of bytes buffered while searching for a cut, after which iteration fails with [`EncodeReaderError::BufferLimit`].
+    /// The buffered text can exceed the limit by up to one block.
+    #[inline(always)]
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered.max(1);
+        self
+    }
+
+    /// Reads the next block and appends the complete utf-8 sequences to the pending text.
+    #[inline(never)]
+    fn fill(&mut self) -> Result<(), EncodeReaderError> {
+        let start = self.bytes.len();
+        self.bytes.resize(start + self.buffer_size, 0);
+        let read = loop {
+            match self.reader.read(&mut self.bytes[start..]) {
+                Ok(read) => break read,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.bytes.truncate(start);
+                    return Err(error.into());
+                }
+            }
+        };
+        self.bytes.truncate(start + read);
+        let offset = self.position + self.pending.len();
+        if read == 0 {
+            self.finished = true;
+            if !self.bytes.is_empty() {
+                return Err(EncodeReaderError::InvalidUtf8(offset));
+            }
+            return Ok(());
+        }
+        let valid = match core::str::from_utf8(&self.bytes) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(error) => return Err(EncodeReaderError::InvalidUtf8(offset + error.valid_up_to())),
+        };
+        if let Ok(text) = core::str::from_utf8(&self.bytes[..valid]) {
+            self.pending.push_str(text);
+        }
+        self.bytes.drain(..valid);
+        Ok(())
+    }
+
+    /// Normalizes and splits the given text at byte offset `offset` in the input like during encoding, and calls `f` with the parts.
+    ///
+    /// `last` is `true` if the text ends the input.
+    #[inline(never)]
+    fn with_parts<T>(
+        &self, text: &str, offset: usize, last: bool, f: impl FnOnce(&mut [TextPart]) -> T,
+    ) -> T {
+        let mut chunks = Vec::new();
+        self.tokenizer
+            .extract_parts_into(text, &self.specials, offset, last, None, &mut chunks);
+        let mut parts = self.tokenizer.split_parts(&chunks, &self.specials);
+        f(&mut parts)
+    }
+
+    /// Calls `f` with the byte offsets in the pending text at the starts of the last parts of the split, from the last to the first,
+    /// leaving enough text after each offset for matching special tokens, and with the parts of the pending text.
+    #[inline(never)]
+    fn with_cut_candidates<T>(&self, f: impl FnOnce(&[usize], &[TextPart]) -> T) -> T {
+        let text = self.pending.as_str();
+        let mut alignments = Vec::new();
+        let mut chunks = Vec::new();
+        self.tokenizer.extract_parts_into(
+            text,
+            &self.specials,
+            self.position,
+            false,
+            Some(&mut alignments),
+            &mut chunks,
+        );
+        let parts = self.tokenizer.split_parts(&chunks, &self.specials);
+        let mut candidates = Vec::new();
+        // the last parts are kept since they can change with the following text
+        for part in parts.iter().rev().skip(1) {
+            let (cut, _) = map_offset(&alignments, (part.start, part.start));
+            if cut == 0 || candidates.len() == Self::MAX_CUT_ATTEMPTS {
+                break;
+            }
+            if text.len() - cut < self.lookahead || !text.is_char_boundary(cut) {
+                continue;
+            }
+            candidates.push(cut);
+        }
+        f(&candidates, &parts)
+    }
+
+    /// Returns the byte offset in the pending text at which the text before can be encoded independently of the text after,
+    /// or `None` if no such offset is found.
+    #[inline(never)]
+    fn find_cut(&self) -> Option<usize> {
+        let text = self.pending.as_str();
+        self.with_cut_candidates(|candidates, parts| {
+            candidates.iter().copied().find(|&cut| {
+                self.with_parts(&text[..cut], self.position, false, |left| {
+                    self.with_parts(&text[cut..], self.position + cut, false, |right| {
+                        left.len() + right.len() == parts.len()
+                            && left
+                                .iter()
+                                .chain(right.iter())
+                                .zip(parts)
+                                .all(|(a, b)| a.text == b.text && a.special == b.special)
+                    })
+                })
+            })
+        })
+    }
+
+    /// Encodes the given text at byte offset `offset` in the input.
+    #[inline(always)]
+    fn encode(&self, text: &str, offset: usize, last: bool) -> Result<Vec<TokenId>, EncodeError> {
+        self.with_parts(text, offset, last, |parts| self.tokenizer.encoder.encode(text, parts))
+    }
+}
+impl<R: Read> Iterator for EncodeReader<'_, R> {
+    type Item = Result<Vec<TokenId>, EncodeReaderError>;
+
+    #[inline(never)]
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.finished {
+                if self.pending.is_empty() {
+                    return None;
+                }
+                let text = core::mem::take(&mut self.pending);
+                return Some(self.encode(&text, self.position, true).map_err(Into::into));
+            }
+            if let Err(error) = self.fill() {
+                self.finished = true;
+                self.pending.clear();
+                return Some(Err(error));
+            }
+            if self.finished || self.pending.len() < self.attempt {
+                continue;
+            }
+            let cut = match self.find_cut() {
+                Some(cut) => cut,
+                None if self.pending.len() >= self.max_buffered => {
+                    self.finished = true;
+                    self.pending.clear();
+                    return Some(Err(EncodeReaderError::BufferLimit(self.max_buffered)));
+                }
+                None => {
+                    self.attempt = (self.pending.len() * 2).min(self.max_buffered);
+                    continue;
+                }
+            };
+            let tokens = self.encode(&self.pending[..cut], self.position, false);
+            self.pending.drain(..cut);
+            self.position += cut;
+            self.attempt = self.buffer_size;
+            match tokens {
+                Ok(tokens) if tokens.is_empty() => continue,
+                Ok(tokens) => return Some(Ok(tokens)),
+                Err(error) => {
+                    self.finished = true;
+                    self.pending.clear();
+                    return Some(Err(error.into()));
+                }
+            }
+        }
+    }
+}
+
+impl Kitoken {
+    /// Encodes the text read from the given reader into a sequence of tokens with bounded memory.
+    ///
+    /// `encode_specials` specifies which special tokens are tokenized with the special vocabulary instead of the regular vocabulary.
+    ///
+    /// Returns an iterator over chunks of tokens, see [`EncodeReader`] for details. Post-tokenization processing is not applied.
+    #[inline(always)]
+    pub fn encode_reader<R: Read>(
+        &self, reader: R, encode_specials: impl SpecialTokenKinds,
+    ) -> EncodeReader<'_, R> {
+        EncodeReader::new(self, reader, encode_specials)
+    }
+}
@@ -62,6 +62,21 @@ impl Regex {
         self.regex.replace_all(text, replace).into_owned()
     }
 
+    /// Returns the byte range and the expanded replacement of each match like [`replace_all`](Self::replace_all).
+    #[inline(always)]
+    pub(crate) fn replacements(&self, text: &str, replace: &str) -> Vec<(usize, usize, String)> {
+        self.regex
+            .captures_iter(text)
+            .map(|captures| captures.unwrap())
+            .map(|captures| {
+                let mut replacement = String::new();
+                captures.expand(replace, &mut replacement);
+                let range = captures.get(0).unwrap().range();
+                (range.start, range.end, replacement)
+            })
+            .collect()
+    }
+
     #[inline(always)]
     pub(crate) fn escape(&self) -> Cow<'_, str> {
         fancy_regex::escape(&self.pattern)
@@ -0,0 +1,137 @@
+//! Randomized encoding for subword regularization.
+
+use alloc::vec::Vec;
+
+use crate::{EncodeError, Kitoken, SpecialTokenKinds, TokenId};
+
+/// Seeded pseudo-random number generator for randomized encoding.
+///
+/// Uses the SplitMix64 algorithm, the same seed always produces the same sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+impl Rng {
+    #[inline(always)]
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Returns the next random number.
+    #[inline(always)]
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns the next random number in `[0, 1)`.
+    #[inline(always)]
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+/// Segmentation of a text with its log-probability.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Segmentation {
+    /// The tokens of the segmentation.
+    pub tokens:   Vec<TokenId>,
+    /// The log-probability of the segmentation, the sum of the scores of its tokens.
+    pub log_prob: f64,
+}
+
+/// Merge dropout state for BPE subword regularization.
+#[derive(Debug, Clone)]
+pub(crate) struct Dropout {
+    probability: f64,
+    rng:         Rng,
+}
+impl Dropout {
+    #[inline(always)]
+    pub fn new(probability: f32, seed: u64) -> Self {
+        Self {
+            probability: probability.clamp(0.0, 1.0) as f64,
+            rng:         Rng::new(seed),
+        }
+    }
+
+    /// Returns `true` if the next merge should be skipped.
+    #[inline(always)]
+    pub fn skip(&mut self) -> bool {
+        self.rng.next_f64() < self.probability
+    }
+}
+
+impl Kitoken {
+    /// Encodes the given text into a sequence of tokens, randomly skipping merges with the given dropout probability.
+    ///
+    /// Works like [`encode`](Self::encode), but skips each merge candidate with probability `dropout`, like BPE-dropout in HuggingFace Tokenizers.
+    /// Skipped merges are reconsidered after the next merge. The same `seed` always produces the same tokens.
+    /// A dropout of `0.0` produces the same tokens as [`encode`](Self::encode), a dropout of `1.0` leaves the input unmerged.
+    /// Only byte pair models use merges.
+    ///
+    /// Returns a list of tokens, or an error if the model is not a byte pair model, or if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_dropout(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds, dropout: f32,
+        seed: u64,
+    ) -> Result<Vec<TokenId>, EncodeError> {
+        let encoder = self.encoder.as_dropout().ok_or(EncodeError::UnsupportedModel)?;
+        let text = text.as_ref();
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let mut result = encoder.encode_dropout(text, &mut parts, Dropout::new(dropout, seed))?;
+        self.config.process(&mut result);
+        Ok(result)
+    }
+
+    /// Encodes the given text into the `n` best segmentations with their log-probabilities, ordered from best to worst.
+    ///
+    /// Works like `NBestEncode` in SentencePiece. Each segmentation is processed like the output of [`encode`](Self::encode).
+    /// Only unigram models score segmentations.
+    ///
+    /// Returns a list of segmentations, or an error if the model is not a unigram model, or if no token for a part exists in the encoder,
+    /// and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_nbest(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds, n: usize,
+    ) -> Result<Vec<Segmentation>, EncodeError> {
+        let encoder = self.encoder.as_scored().ok_or(EncodeError::UnsupportedModel)?;
+        let text = text.as_ref();
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let mut result = encoder.encode_nbest(&mut parts, n)?;
+        for segmentation in &mut result {
+            self.config.process(&mut segmentation.tokens);
+        }
+        Ok(result)
+    }
+
+    /// Encodes the given text into a randomly sampled segmentation with its log-probability.
+    ///
+    /// Works like `SampleEncode` in SentencePiece for subword regularization. Samples from all segmentations if `nbest` is `0`,
+    /// and from the `nbest` best segmentations otherwise. The probability of each segmentation is proportional to its probability
+    /// raised to the power of `alpha`, lower values sample more uniformly. The same `seed` always produces the same segmentation.
+    /// Only unigram models score segmentations.
+    ///
+    /// Returns a segmentation, or an error if the model is not a unigram model, or if no token for a part exists in the encoder,
+    /// and the configuration has no unknown token or skip fallback set.
+    #[inline(never)]
+    pub fn encode_sample(
+        &self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds, alpha: f32,
+        nbest: usize, seed: u64,
+    ) -> Result<Segmentation, EncodeError> {
+        let encoder = self.encoder.as_scored().ok_or(EncodeError::UnsupportedModel)?;
+        let text = text.as_ref();
+        let encode_specials = encode_specials.as_kinds(&self.meta);
+        let chunks = self.extract_parts(text, encode_specials, None);
+        let mut parts = self.split_parts(&chunks, encode_specials);
+        let mut rng = Rng::new(seed);
+        let mut result = encoder.encode_sample(&mut parts, alpha, nbest, &mut rng)?;
+        self.config.process(&mut result.tokens);
+        Ok(result)
+    }
+}
@@ -8,12 +8,65 @@ use std::io::{Read, Result as IOResult, Write};
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
+use serde::Deserialize;
+
 #[cfg(feature = "convert-detect")]
 use crate::convert::ConversionError;
-use crate::{Definition, InitializationError, Kitoken};
+use crate::{
+    Configuration, Decoding, Definition, Fallback, InitializationError, Kitoken, Metadata, Model,
+    Normalization, Processing, SpecialVocab, Split, Template, TemplateTypeIds,
+};
 
 const MAGIC: &[u8] = b"kitoken";
-const VERSION: &[u8] = &[0, 1];
+const VERSION: &[u8] = &[0, 2];
+/// Version of definitions serialized before the template type ids were added to the configuration.
+const VERSION_0_1: &[u8] = &[0, 1];
+
+/// Tokenizer definition of version 0.1, before the template type ids were added to the configuration.
+#[derive(Deserialize)]
+struct DefinitionV0_1 {
+    meta:     Metadata,
+    model:    Model,
+    specials: SpecialVocab,
+    config:   ConfigurationV0_1,
+}
+/// Tokenizer configuration of version 0.1.
+#[derive(Deserialize)]
+struct ConfigurationV0_1 {
+    fallback:      Vec<Fallback>,
+    normalization: Vec<Normalization>,
+    split:         Vec<Split>,
+    processing:    Vec<Processing>,
+    decoding:      Vec<Decoding>,
+    templates:     Vec<Template>,
+}
+impl From<DefinitionV0_1> for Definition {
+    #[inline(never)]
+    fn from(definition: DefinitionV0_1) -> Self {
+        let ConfigurationV0_1 {
+            fallback,
+            normalization,
+            split,
+            processing,
+            decoding,
+            templates,
+        } = definition.config;
+        Self {
+            meta:     definition.meta,
+            model:    definition.model,
+            specials: definition.specials,
+            config:   Configuration {
+                fallback,
+                normalization,
+                split,
+                processing,
+                decoding,
+                templates,
+                type_ids: TemplateTypeIds::default(),
+            },
+        }
+    }
+}
 
 /// Errors encountered when deserializing the tokenizer.
 #[non_exhaustive]
@@ -61,18 +114,7 @@ impl Definition {
     /// Deserializes the tokenizer definition from bytes.
     /// The format is detected automatically when the `convert-detect` feature is enabled.
     pub fn from_slice(slice: &[u8]) -> Result<Self, DeserializationError> {
-        if slice.len() < MAGIC.len() + VERSION.len() {
-            return Err(DeserializationError::InvalidData("invalid size".to_string()));
-        }
-        if &slice[..MAGIC.len()] != MAGIC {
-            return Err(DeserializationError::InvalidData("invalid magic".to_string()));
-        }
-        if &slice[MAGIC.len()..MAGIC.len() + VERSION.len()] != VERSION {
-            return Err(DeserializationError::InvalidData("invalid version".to_string()));
-        }
-        let definition = postcard::from_bytes(&slice[MAGIC.len() + VERSION.len()..])
-            .map_err(|e| DeserializationError::InvalidData(e.to_string()))?;
-        Ok(definition)
+        Self::from_kitoken_slice(slice).map_err(DeserializationError::InvalidData)
     }
 
     #[cfg(feature = "convert-detect")]
@@ -81,17 +123,7 @@ impl Definition {
     pub fn from_slice(slice: &[u8]) -> Result<Self, DeserializationError> {
         let formats = &[
             |slice: &[u8]| {
-                if slice.len() < MAGIC.len() + VERSION.len() {
-                    return Err(ConversionError::InvalidData("invalid size".to_string()));
-                }
-                if &slice[..MAGIC.len()] != MAGIC {
-                    return Err(ConversionError::InvalidData("invalid magic".to_string()));
-                }
-                if &slice[MAGIC.len()..MAGIC.len() + VERSION.len()] != VERSION {
-                    return Err(ConversionError::InvalidData("invalid version".to_string()));
-                }
-                postcard::from_bytes(&slice[MAGIC.len() + VERSION.len()..])
-                    .map_err(|e| ConversionError::InvalidData(e.to_string()))
+                Self::from_kitoken_slice(slice).map_err(ConversionError::InvalidData)
             },
             #[cfg(feature = "convert-tiktoken")]
             Definition::from_tiktoken_slice,
@@ -108,6 +140,27 @@ impl Definition {
             .ok_or_else(|| DeserializationError::InvalidData("unknown format".to_string()))
     }
 
+    /// Deserializes the tokenizer definition from bytes in the Kitoken format.
+    ///
+    /// Returns the definition, or a message describing why the data is invalid.
+    fn from_kitoken_slice(slice: &[u8]) -> Result<Self, String> {
+        if slice.len() < MAGIC.len() + VERSION.len() {
+            return Err("invalid size".to_string());
+        }
+        if &slice[..MAGIC.len()] != MAGIC {
+            return Err("invalid magic".to_string());
+        }
+        let (version, data) = slice[MAGIC.len()..].split_at(VERSION.len());
+        if version == VERSION_0_1 {
+            let definition = postcard::from_bytes::<DefinitionV0_1>(data);
+            return definition.map(Definition::from).map_err(|e| e.to_string());
+        }
+        if version != VERSION {
+            return Err("invalid version".to_string());
+        }
+        postcard::from_bytes(data).map_err(|e| e.to_string())
+    }
+
     /// Serializes the tokenizer definition to a writer.
     #[cfg(feature = "std")]
     pub fn to_writer<W: Write>(&self, writer: &mut W) -> IOResult<()> {
@@ -0,0 +1,138 @@
+//! Reusable encoding session.
+
+use alloc::vec::Vec;
+
+use crate::{EncodeBuffers, EncodeError, Kitoken, SpecialTokenKinds, TextPart, TokenId};
+
+/// Encoding session reusing its buffers across calls.
+///
+/// Created with [`Kitoken::encoder_state`]. Keeps the intermediate parts and the scratch buffers of the encoder between calls
+/// to avoid repeated allocations when encoding many texts. Sessions are meant to be kept per thread.
+#[derive(Debug)]
+pub struct EncodeSession<'a> {
+    tokenizer: &'a Kitoken,
+
+    buffers: EncodeBuffers,
+    chunks:  Vec<TextPart<'static>>,
+    parts:   Vec<TextPart<'static>>,
+    tokens:  Vec<TokenId>,
+}
+impl<'a> EncodeSession<'a> {
+    #[inline(always)]
+    pub(crate) fn new(tokenizer: &'a Kitoken) -> Self {
+        Self {
+            tokenizer,
+            buffers: EncodeBuffers::default(),
+            chunks: Vec::new(),
+            parts: Vec::new(),
+            tokens: Vec::new(),
+        }
+    }
+
+    /// Encodes the given text into a sequence of tokens.
+    ///
+    /// Works like [`Kitoken::encode`], reusing the buffers of the session.
+    ///
+    /// Returns a list of tokens, or an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    #[inline(always)]
+    pub fn encode(
+        &mut self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+    ) -> Result<Vec<TokenId>, EncodeError> {
+        let mut result = Vec::new();
+        self.encode_into(text, encode_specials, &mut result)?;
+        Ok(result)
+    }
+
+    /// Encodes the given text into a sequence of tokens and appends them to `result`.
+    ///
+    /// Works like [`Kitoken::encode`], reusing the buffers of the session. Post-tokenization processing is applied to the appended tokens only.
+    ///
+    /// Returns an error if no token for a part exists in the encoder, and the configuration has no unknown token or skip fallback set.
+    /// On error, `result` is left unchanged.
+    #[inline(never)]
+    pub fn encode_into(
+        &mut self, text: impl AsRef<str>, encode_specials: impl SpecialTokenKinds,
+        result: &mut Vec<TokenId>,
+    ) -> Result<(), EncodeError> {
+        let tokenizer = self.tokenizer;
+        let text = text.as_ref();
+        let encode_specials = encode_specials.as_kinds(&tokenizer.meta);
+        let mut chunks = recycle(core::mem::take(&mut self.chunks));
+        tokenizer.extract_parts_into(text, encode_specials, 0, true, None, &mut chunks);
+        let mut parts = recycle(core::mem::take(&mut self.parts));
+        tokenizer.split_parts_into(&chunks, encode_specials, &mut parts);
+        let len = result.len();
+        let encoded = if tokenizer.config.processing.is_empty() {
+            tokenizer.encoder.encode_into(&mut parts, result, &mut self.buffers)
+        } else {
+            self.tokens.clear();
+            let encoded =
+                tokenizer.encoder.encode_into(&mut parts, &mut self.tokens, &mut self.buffers);
+            tokenizer.config.process(&mut self.tokens);
+            result.extend_from_slice(&self.tokens);
+            encoded
+        };
+        if encoded.is_err() {
+            result.truncate(len);
+        }
+        self.parts = recycle(parts);
+        self.chunks = recycle(chunks);
+        encoded
+    }
+}
+
+/// Clears the list of parts and reuses its allocation for parts of a different lifetime.
+#[inline(always)]
+fn recycle<'b>(mut parts: Vec<TextPart<'_>>) -> Vec<TextPart<'b>> {
+    parts.clear();
+    let mut parts = core::mem::ManuallyDrop::new(parts);
+    // SAFETY: the list is empty, and the element types only differ in lifetime and have the same layout
+    unsafe { Vec::from_raw_parts(parts.as_mut_ptr().cast(), 0, parts.capacity()) }
+}
+
+impl Kitoken {
+    /// Creates an encoding session reusing its buffers across calls.
+    ///
+    /// See [`EncodeSession`] for details.
+    #[inline(always)]
+    pub fn encoder_state(&self) -> EncodeSession<'_> {
+        EncodeSession::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recycle_keeps_capacity() {
+        let text = alloc::string::String::from("text");
+        let mut parts = Vec::with_capacity(16);
+        parts.push(TextPart {
+            text:    text.as_str().into(),
+            special: 0,
+            start:   0,
+        });
+        let parts: Vec<TextPart<'static>> = recycle(parts);
+        assert!(parts.is_empty());
+        assert!(parts.capacity() >= 16);
+    }
+
+    #[test]
+    fn test_session_keeps_capacity() {
+        let tokenizer = Kitoken::from_definition(crate::Definition {
+            meta:     Default::default(),
+            model:    crate::Model::Bytes { offset: 0 },
+            specials: Default::default(),
+            config:   Default::default(),
+        })
+        .unwrap();
+        let mut session = tokenizer.encoder_state();
+        session.encode("text to encode", false).unwrap();
+        let (chunks, parts) = (session.chunks.capacity(), session.parts.capacity());
+        assert!(chunks > 0 && parts > 0);
+        session.encode("more text", false).unwrap();
+        assert_eq!(session.chunks.capacity(), chunks);
+        assert_eq!(session.parts.capacity(), parts);
+    }
+}
@@ -0,0 +1,84 @@
+//! Utilities for training Kitoken definitions from text.
+//!
+//! The trainers pre-tokenize the training text with the normalization and split configuration of the trained definition,
+//! the same way as [`Kitoken::encode`](crate::Kitoken::encode), so the trained tokenizer sees the same parts during encoding.
+
+use alloc::string::{String, ToString};
+
+use hashbrown::HashMap;
+
+use crate::{
+    Configuration, InitializationError, Kitoken, Metadata, Model, SpecialTokenKind, SpecialVocab,
+    Token, TokenId,
+};
+
+mod bytepair;
+mod unigram;
+mod wordpiece;
+
+pub use bytepair::*;
+pub use unigram::*;
+pub use wordpiece::*;
+
+/// Errors encountered when the training fails.
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum TrainingError {
+    /// The vocabulary size is too small for the initial vocabulary and the special tokens.
+    #[error("vocabulary size too small, at least {0} required")]
+    VocabSizeTooSmall(usize),
+    /// The training produced no tokens.
+    #[error("training produced no tokens")]
+    EmptyVocab,
+    /// The training produced invalid token scores.
+    #[error("training produced invalid scores")]
+    InvalidScores,
+    /// The tokenizer failed to initialize.
+    #[error("{0}")]
+    InitializationError(InitializationError),
+}
+impl From<InitializationError> for TrainingError {
+    fn from(e: InitializationError) -> Self {
+        Self::InitializationError(e)
+    }
+}
+
+/// Counts the parts of the given texts, normalized and split like during encoding.
+///
+/// Special tokens of all kinds are removed from the texts and not counted.
+#[inline(never)]
+pub(crate) fn count_words(
+    texts: impl IntoIterator<Item = impl AsRef<str>>, specials: &SpecialVocab,
+    config: &Configuration,
+) -> Result<HashMap<String, u64>, TrainingError> {
+    const KINDS: &[SpecialTokenKind] = &[
+        SpecialTokenKind::Control,
+        SpecialTokenKind::Priority,
+        SpecialTokenKind::Unknown,
+    ];
+    // place the byte ids after the special ids to avoid collisions
+    let offset = specials.iter().map(|special| special.id.saturating_add(1)).max().unwrap_or(0);
+    let model = Model::Bytes { offset };
+    let tokenizer = Kitoken::new(model, specials.clone(), config.clone(), Metadata::default())?;
+    let mut words = HashMap::<String, u64>::new();
+    for text in texts {
+        let chunks = tokenizer.extract_parts(text.as_ref(), KINDS, None);
+        for part in tokenizer.split_parts(&chunks, KINDS) {
+            if part.special != Token::INVALID {
+                continue;
+            }
+            if let Some(count) = words.get_mut(part.text.as_ref()) {
+                *count += 1;
+            } else {
+                words.insert(part.text.to_string(), 1);
+            }
+        }
+    }
+    Ok(words)
+}
+
+/// Returns the token ids not used by the given special tokens in ascending order.
+#[inline(never)]
+pub(crate) fn free_ids(specials: &SpecialVocab) -> impl Iterator<Item = TokenId> + '_ {
+    (0..).filter(|id| !specials.iter().any(|special| special.id == *id))
+}
@@ -0,0 +1,198 @@
+//! Trainer for byte pair models.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+
+use hashbrown::{HashMap, HashSet};
+
+use super::{TrainingError, count_words, free_ids};
+use crate::{Configuration, Definition, Metadata, Model, SpecialVocab, TokenBytes, Vocab};
+
+type Pair = (u32, u32);
+
+/// Merge candidate ordered by count, then by the ranks of its tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Candidate {
+    count: u64,
+    pair:  Pair,
+}
+impl PartialOrd for Candidate {
+    #[inline(always)]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    #[inline(always)]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.count
+            .cmp(&other.count)
+            .then_with(|| Reverse(self.pair).cmp(&Reverse(other.pair)))
+    }
+}
+
+/// Trainer for byte pair models.
+///
+/// Learns a byte-level [`Model::BytePair`] vocabulary like the BPE trainer of HuggingFace Tokenizers. The vocabulary starts with
+/// all single bytes, and the most frequent pair of adjacent tokens in the pre-tokenized training text is merged into a new token
+/// until the vocabulary reaches the target size or no pair occurs at least `min_frequency` times.
+///
+/// The trained definition uses the given special tokens and configuration. Tokens get the ids not used by the special tokens in ascending order.
+#[derive(Debug, Clone)]
+pub struct BpeTrainer {
+    /// The target vocabulary size including the special tokens.
+    pub vocab_size:    usize,
+    /// The special tokens. Removed from the training text before pre-tokenization.
+    pub specials:      SpecialVocab,
+    /// The configuration used for pre-tokenizing the training text and included in the trained definition.
+    pub config:        Configuration,
+    /// The minimum number of occurrences of a pair to be merged.
+    pub min_frequency: u64,
+}
+impl BpeTrainer {
+    /// Creates a trainer for the given target vocabulary size, special tokens and configuration.
+    #[inline(never)]
+    pub fn new(vocab_size: usize, specials: SpecialVocab, config: Configuration) -> Self {
+        Self {
+            vocab_size,
+            specials,
+            config,
+            min_frequency: 1,
+        }
+    }
+
+    /// Trains a definition on the given texts.
+    ///
+    /// Returns the trained definition, or an error if the vocabulary size is smaller than the number of single bytes
+    /// and special tokens, or if the configuration or the special tokens are invalid.
+    #[inline(never)]
+    pub fn train(
+        &self, texts: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Definition, TrainingError> {
+        let required = 256 + self.specials.len();
+        if self.vocab_size < required {
+            return Err(TrainingError::VocabSizeTooSmall(required));
+        }
+        let words = count_words(texts, &self.specials, &self.config)?;
+        let mut words = words
+            .into_iter()
+            .map(|(word, count)| (word.bytes().map(u32::from).collect::<Vec<_>>(), count))
+            .collect::<Vec<_>>();
+        words.sort_unstable();
+
+        let mut tokens = (0..=u8::MAX).map(|byte| Vec::from([byte])).collect::<Vec<TokenBytes>>();
+        let size = self.vocab_size - self.specials.len();
+        merge_pairs(&mut words, &mut tokens, size, self.min_frequency, |a, b| [a, b].concat());
+
+        let vocab = tokens
+            .into_iter()
+            .zip(free_ids(&self.specials))
+            .map(|(bytes, id)| (bytes, id).into())
+            .collect::<Vocab>();
+        let mut specials = self.specials.clone();
+        specials.sort();
+        Ok(Definition {
+            meta: Metadata::default(),
+            model: Model::BytePair {
+                vocab,
+                chars: false,
+            },
+            specials,
+            config: self.config.clone(),
+        })
+    }
+}
+
+/// Merges the most frequent pair of adjacent tokens in the words into a new token until `tokens` reaches `size` tokens
+/// or no pair occurs at least `min_frequency` times.
+///
+/// The words are sequences of indices into `tokens` with their counts, and `merge` returns the bytes of the token merged from a pair.
+/// Pairs with the same count are merged in the order of the ranks of their tokens.
+#[inline(never)]
+pub(crate) fn merge_pairs(
+    words: &mut [(Vec<u32>, u64)], tokens: &mut Vec<TokenBytes>, size: usize, min_frequency: u64,
+    merge: impl Fn(&[u8], &[u8]) -> TokenBytes,
+) {
+    let mut ranks = tokens
+        .iter()
+        .zip(0..)
+        .map(|(bytes, rank)| (bytes.clone(), rank))
+        .collect::<HashMap<TokenBytes, u32>>();
+
+    let mut counts = HashMap::<Pair, u64>::new();
+    let mut locations = HashMap::<Pair, HashSet<usize>>::new();
+    for (i, (symbols, count)) in words.iter().enumerate() {
+        for pair in symbols.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += count;
+            locations.entry((pair[0], pair[1])).or_default().insert(i);
+        }
+    }
+    let mut queue = counts
+        .iter()
+        .map(|(&pair, &count)| Candidate { count, pair })
+        .collect::<BinaryHeap<_>>();
+
+    let min_frequency = min_frequency.max(1);
+    while tokens.len() < size {
+        let Some(Candidate { count, pair }) = queue.pop() else {
+            break;
+        };
+        let current = counts.get(&pair).copied().unwrap_or(0);
+        if current != count {
+            if current > 0 {
+                queue.push(Candidate {
+                    count: current,
+                    pair,
+                });
+            }
+            continue;
+        }
+        if count < min_frequency {
+            break;
+        }
+
+        let bytes = merge(&tokens[pair.0 as usize], &tokens[pair.1 as usize]);
+        let merged = if let Some(&rank) = ranks.get(&bytes) {
+            rank
+        } else {
+            let rank = tokens.len() as u32;
+            ranks.insert(bytes.clone(), rank);
+            tokens.push(bytes);
+            rank
+        };
+
+        let mut changed = HashSet::<Pair>::new();
+        for i in locations.remove(&pair).unwrap_or_default() {
+            let (symbols, count) = &mut words[i];
+            for old in symbols.windows(2) {
+                let old = (old[0], old[1]);
+                let remaining = counts.get_mut(&old).unwrap();
+                *remaining -= *count;
+                if *remaining == 0 {
+                    counts.remove(&old);
+                }
+                changed.insert(old);
+            }
+            let mut j = 0;
+            while j + 1 < symbols.len() {
+                if (symbols[j], symbols[j + 1]) == pair {
+                    symbols[j] = merged;
+                    symbols.remove(j + 1);
+                }
+                j += 1;
+            }
+            for new in symbols.windows(2) {
+                let new = (new[0], new[1]);
+                *counts.entry(new).or_default() += *count;
+                locations.entry(new).or_default().insert(i);
+                changed.insert(new);
+            }
+        }
+        for pair in changed {
+            if let Some(&count) = counts.get(&pair) {
+                queue.push(Candidate { count, pair });
+            }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Trainer for unigram models.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::{TrainingError, count_words, free_ids};
+use crate::{
+    Configuration, Definition, Fallback, Metadata, Model, Scores, SpecialTokenKind, SpecialVocab,
+    Token, TokenBytes, Vocab,
+};
+
+/// Candidate piece with its log-probability.
+#[derive(Debug, Clone)]
+struct Piece {
+    text:  String,
+    score: f64,
+}
+impl Piece {
+    /// Returns `true` if the piece is a single character. Single characters are never removed.
+    #[inline(always)]
+    fn is_char(&self) -> bool {
+        self.text.chars().nth(1).is_none()
+    }
+}
+
+/// Segmentation lattice of a word over the current pieces.
+struct Lattice {
+    /// The edges ending at each character boundary as `(start, piece)`.
+    edges: Vec<Vec<(usize, usize)>>,
+}
+impl Lattice {
+    #[inline(never)]
+    fn new(word: &str, index: &HashMap<&str, usize>, max_piece_chars: usize) -> Self {
+        let mut bounds = word.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+        bounds.push(word.len());
+        let mut edges = Vec::with_capacity(bounds.len());
+        edges.push(Vec::new());
+        for end in 1..bounds.len() {
+            let mut ending = Vec::new();
+            for start in end.saturating_sub(max_piece_chars)..end {
+                if let Some(&piece) = index.get(&word[bounds[start]..bounds[end]]) {
+                    ending.push((start, piece));
+                }
+            }
+            edges.push(ending);
+        }
+        Self { edges }
+    }
+
+    /// Returns the number of character boundaries.
+    #[inline(always)]
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Returns the pieces of the best segmentation without the `exclude` piece, or `None` if no segmentation exists.
+    #[inline(never)]
+    fn viterbi(&self, pieces: &[Piece], exclude: Option<usize>) -> Option<Vec<usize>> {
+        let mut best = Vec::<(f64, usize, usize)>::with_capacity(self.len());
+        best.push((0.0, 0, usize::MAX));
+        for ending in &self.edges[1..] {
+            let mut current = (f64::NEG_INFINITY, 0, usize::MAX);
+            for &(start, piece) in ending {
+                if Some(piece) == exclude {
+                    continue;
+                }
+                let score = best[start].0 + pieces[piece].score;
+                if score > current.0 {
+                    current = (score, start, piece);
+                }
+            }
+            best.push(current);
+        }
+        let mut path = Vec::new();
+        let mut end = self.len() - 1;
+        while end > 0 {
+            let (score, start, piece) = best[end];
+            if score == f64::NEG_INFINITY {
+                return None;
+            }
+            path.push(piece);
+            end = start;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+/// Returns `log(exp(a) + exp(b))`.
+#[inline(always)]
+fn log_add(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let (max, min) = if a > b { (a, b) } else { (b, a) };
+    max + libm::log1p(libm::exp(min - max))
+}
+
+/// Trainer for unigram models.
+///
+/// Learns a [`Model::Unigram`] vocabulary with the EM algorithm like the unigram trainer of SentencePiece. The candidate pieces
+/// are seeded with the most frequent substrings of the pre-tokenized training text. Each round re-estimates the piece scores
+/// with `em_iterations` EM steps, and then removes the pieces whose removal reduces the likelihood the least, keeping
+/// `shrinking_factor` of the pieces, until the vocabulary reaches the target size. Single characters are always kept.
+/// The rarest characters beyond `character_coverage` of the training text are excluded from the pieces and left to the fallback.
+///
+/// If `byte_fallback` is set, a token for every byte without a single-byte piece is added, and [`Fallback::Bytes`] is added to the configuration.
+/// If a special token of kind [`SpecialTokenKind::Unknown`] is given, [`Fallback::Unknown`] is added to the configuration.
+/// Tokens get the ids not used by the special tokens in ascending order, starting with the byte tokens followed by the pieces by descending score.
+#[derive(Debug, Clone)]
+pub struct UnigramTrainer {
+    /// The target vocabulary size including the special tokens and the byte tokens.
+    pub vocab_size:         usize,
+    /// The special tokens. Removed from the training text before pre-tokenization.
+    pub specials:           SpecialVocab,
+    /// The configuration used for pre-tokenizing the training text and included in the trained definition.
+    pub config:             Configuration,
+    /// Whether to add tokens for all bytes and encode unknown characters as bytes.
+    pub byte_fallback:      bool,
+    /// The maximum number of characters in a piece.
+    pub max_piece_chars:    usize,
+    /// The fraction of characters in the training text covered by the pieces.
+    pub character_coverage: f64,
+    /// The number of multi-character candidate pieces to seed the training with in addition to the single characters.
+    pub seed_size:          usize,
+    /// The fraction of pieces kept in each pruning round.
+    pub shrinking_factor:   f64,
+    /// The number of EM steps in each round.
+    pub em_iterations:      usize,
+}
+impl UnigramTrainer {
+    /// Pieces with fewer expected occurrences are removed after each EM step.
+    const MIN_EXPECTED_COUNT: f64 = 0.5;
+
+    /// Creates a trainer for the given target vocabulary size, special tokens and configuration.
+    #[inline(never)]
+    pub fn new(vocab_size: usize, specials: SpecialVocab, config: Configuration) -> Self {
+        Self {
+            vocab_size,
+            specials,
+            config,
+            byte_fallback: false,
+            max_piece_chars: 16,
+            character_coverage: 0.9995,
+            seed_size: 1_000_000,
+            shrinking_factor: 0.75,
+            em_iterations: 2,
+        }
+    }
+
+    /// Trains a definition on the given texts.
+    ///
+    /// Returns the trained definition, or an error if the vocabulary size is smaller than the number of characters in the training text,
+    /// special tokens and byte tokens, if the training text yields no pieces or invalid scores, or if the configuration or the special tokens are invalid.
+    #[inline(never)]
+    pub fn train(
+        &self, texts: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Definition, TrainingError> {
+        let words = count_words(texts, &self.specials, &self.config)?;
+        let chars = self.covered_chars(&words);
+        let mut covered = HashMap::<String, u64>::new();
+        for (word, count) in &words {
+            for run in word.split(|c| !chars.contains(&c)).filter(|run| !run.is_empty()) {
+                *covered.entry_ref(run).or_default() += count;
+            }
+        }
+        let mut words = covered.into_iter().collect::<Vec<_>>();
+        words.sort_unstable();
+
+        let reserved = self.specials.len() + if self.byte_fallback { 256 } else { 0 };
+        let target = self.vocab_size.saturating_sub(reserved);
+        if target < chars.len() {
+            return Err(TrainingError::VocabSizeTooSmall(chars.len() + reserved));
+        }
+
+        let mut pieces = self.seed_pieces(&words);
+        loop {
+            for _ in 0..self.em_iterations.max(1) {
+                pieces = self.em_step(&words, pieces);
+            }
+            if pieces.len() <= target {
+                break;
+            }
+            pieces = self.prune_pieces(&words, pieces, target);
+        }
+        self.finalize(pieces)
+    }
+
+    /// Returns the most frequent characters of the words covering `character_coverage` of all characters.
+    #[inline(never)]
+    fn covered_chars(&self, words: &HashMap<String, u64>) -> HashSet<char> {
+        let mut counts = HashMap::<char, u64>::new();
+        for (word, count) in words {
+            for c in word.chars() {
+                *counts.entry(c).or_default() += count;
+            }
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        let total = counts.iter().map(|(_, count)| *count).sum::<u64>();
+        let required = total as f64 * self.character_coverage.clamp(0.0, 1.0);
+        let mut accumulated = 0;
+        counts
+            .into_iter()
+            .take_while(|(_, count)| {
+                let take = (accumulated as f64) < required;
+                accumulated += count;
+                take
+            })
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    /// Returns all single characters and the `seed_size` most frequent substrings of the words, scored by their frequency.
+    #[inline(never)]
+    fn seed_pieces(&self, words: &[(String, u64)]) -> Vec<Piece> {
+        let mut counts = HashMap::<&str, u64>::new();
+        for (word, count) in words {
+            let mut bounds = word.char_indices().map(|(i, _)| i).collect::<Vec<_>>();
+            bounds.push(word.len());
+            for start in 0..bounds.len() - 1 {
+                for end in start + 1..bounds.len().min(start + self.max_piece_chars + 1) {
+                    *counts.entry(&word[bounds[start]..bounds[end]]).or_default() += count;
+                }
+            }
+        }
+        let (mut seeds, mut substrings) = counts
+            .into_iter()
+            .partition::<Vec<_>, _>(|(text, _)| text.chars().nth(1).is_none());
+        let weight = |(text, count): &(&str, u64)| count * text.chars().count() as u64;
+        substrings.sort_unstable_by(|a, b| weight(b).cmp(&weight(a)).then(a.0.cmp(b.0)));
+        substrings.truncate(self.seed_size);
+        seeds.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        seeds.extend(substrings);
+
+        let total = libm::log(seeds.iter().map(|(_, count)| *count as f64).sum::<f64>());
+        seeds
+            .into_iter()
+            .map(|(text, count)| Piece {
+                text:  text.into(),
+                score: libm::log(count as f64) - total,
+            })
+            .collect()
+    }
+
+    /// Re-estimates the piece scores from the expected piece counts, and removes the pieces expected less than [`MIN_EXPECTED_COUNT`](Self::MIN_EXPECTED_COUNT) times.
+    #[inline(never)]
+    fn em_step(&self, words: &[(String, u64)], pieces: Vec<Piece>) -> Vec<Piece> {
+        let index = pieces.iter().enumerate().map(|(i, piece)| (piece.text.as_str(), i)).collect();
+        let mut expected = Vec::from_iter(core::iter::repeat_n(0.0, pieces.len()));
+        for (word, count) in words {
+            let lattice = Lattice::new(word, &index, self.max_piece_chars);
+            let mut alpha = Vec::from_iter(core::iter::repeat_n(f64::NEG_INFINITY, lattice.len()));
+            alpha[0] = 0.0;
+            for end in 1..lattice.len() {
+                for &(start, piece) in &lattice.edges[end] {
+                    alpha[end] = log_add(alpha[end], alpha[start] + pieces[piece].score);
+                }
+            }
+            let mut beta = Vec::from_iter(core::iter::repeat_n(f64::NEG_INFINITY, lattice.len()));
+            beta[lattice.len() - 1] = 0.0;
+            for end in (1..lattice.len()).rev() {
+                for &(start, piece) in &lattice.edges[end] {
+                    beta[start] = log_add(beta[start], beta[end] + pieces[piece].score);
+                }
+            }
+            let z = alpha[lattice.len() - 1];
+            if z == f64::NEG_INFINITY {
+                continue;
+            }
+            for (ending, beta) in lattice.edges.iter().zip(&beta).skip(1) {
+                for &(start, piece) in ending {
+                    let log_prob = alpha[start] + pieces[piece].score + beta - z;
+                    expected[piece] += *count as f64 * libm::exp(log_prob);
+                }
+            }
+        }
+        drop(index);
+
+        let pieces = pieces
+            .into_iter()
+            .zip(expected)
+            .filter(|(piece, expected)| *expected >= Self::MIN_EXPECTED_COUNT || piece.is_char())
+            .map(|(piece, expected)| (piece, expected.max(Self::MIN_EXPECTED_COUNT)))
+            .collect::<Vec<_>>();
+        let total = libm::log(pieces.iter().map(|(_, expected)| expected).sum::<f64>());
+        pieces
+            .into_iter()
+            .map(|(piece, expected)| Piece {
+                text:  piece.text,
+                score: libm::log(expected) - total,
+            })
+            .collect()
+    }
+
+    /// Removes the pieces whose removal reduces the likelihood of the best segmentations the least,
+    /// keeping `shrinking_factor` of the pieces, but at least `target` pieces.
+    #[inline(never)]
+    fn prune_pieces(
+        &self, words: &[(String, u64)], pieces: Vec<Piece>, target: usize,
+    ) -> Vec<Piece> {
+        let index = pieces.iter().enumerate().map(|(i, piece)| (piece.text.as_str(), i)).collect();
+        let mut freq = Vec::from_iter(core::iter::repeat_n(0.0, pieces.len()));
+        let mut inverted = Vec::from_iter(core::iter::repeat_n(0.0, pieces.len()));
+        let mut words_total = 0.0;
+        for (word, count) in words {
+            let lattice = Lattice::new(word, &index, self.max_piece_chars);
+            let Some(mut path) = lattice.viterbi(&pieces, None) else {
+                continue;
+            };
+            let count = *count as f64;
+            words_total += count;
+            for &piece in &path {
+                freq[piece] += count;
+            }
+            path.sort_unstable();
+            path.dedup();
+            for piece in path {
+                inverted[piece] += count;
+            }
+        }
+        let sum = freq.iter().sum::<f64>();
+        let log_sum = libm::log(sum);
+
+        let mut candidates = Vec::with_capacity(pieces.len());
+        let mut kept = HashSet::new();
+        for (i, piece) in pieces.iter().enumerate() {
+            if piece.is_char() {
+                kept.insert(i);
+                continue;
+            }
+            if freq[i] == 0.0 {
+                continue;
+            }
+            let lattice = Lattice::new(&piece.text, &index, self.max_piece_chars);
+            let Some(alternatives) = lattice.viterbi(&pieces, Some(i)) else {
+                kept.insert(i);
+                continue;
+            };
+            let probability = inverted[i] / words_total;
+            let log_prob = libm::log(freq[i]) - log_sum;
+            let sum_alternatives = sum + freq[i] * (alternatives.len() as f64 - 1.0);
+            let log_prob_alternatives = alternatives
+                .iter()
+                .map(|&alternative| libm::log(freq[alternative] + freq[i]))
+                .sum::<f64>()
+                - libm::log(sum_alternatives) * alternatives.len() as f64;
+            candidates.push((i, probability * (log_prob - log_prob_alternatives)));
+        }
+        candidates.sort_unstable_by(|(a, a_loss), (b, b_loss)| {
+            b_loss.partial_cmp(a_loss).unwrap_or(Ordering::Equal).then(a.cmp(b))
+        });
+        let size = target.max((pieces.len() as f64 * self.shrinking_factor) as usize);
+        for (i, _) in candidates {
+            if kept.len() >= size {
+                break;
+            }
+            kept.insert(i);
+        }
+        drop(index);
+        pieces
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| kept.contains(i))
+            .map(|(_, p)| p)
+            .collect()
+    }
+
+    /// Builds the definition from the pieces.
+    #[inline(never)]
+    fn finalize(&self, mut pieces: Vec<Piece>) -> Result<Definition, TrainingError> {
+        if pieces.is_empty() {
+            return Err(TrainingError::EmptyVocab);
+        }
+        if pieces.iter().any(|piece| !piece.score.is_finite()) {
+            return Err(TrainingError::InvalidScores);
+        }
+        pieces.sort_unstable_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(a.text.cmp(&b.text))
+        });
+        let min_score = pieces.last().map_or(0.0, |piece| piece.score);
+
+        let mut tokens = Vec::<(TokenBytes, f64)>::with_capacity(pieces.len() + 256);
+        if self.byte_fallback {
+            let existing = pieces.iter().map(|piece| piece.text.as_bytes()).collect::<HashSet<_>>();
+            for byte in 0..=u8::MAX {
+                if !existing.contains(&[byte][..]) {
+                    tokens.push((Vec::from([byte]), min_score));
+                }
+            }
+        }
+        tokens.extend(pieces.into_iter().map(|piece| (piece.text.into_bytes(), piece.score)));
+
+        let mut vocab = tokens
+            .into_iter()
+            .zip(free_ids(&self.specials))
+            .map(|((bytes, score), id)| (Token::from((bytes, id)), score as f32))
+            .collect::<Vec<_>>();
+        vocab.sort_by(|(a, a_score), (b, b_score)| {
+            a_score.total_cmp(b_score).then_with(|| a.id.cmp(&b.id))
+        });
+        let scores = vocab.iter().map(|(_, score)| *score).collect::<Scores>();
+        let vocab = vocab.into_iter().map(|(token, _)| token).collect::<Vocab>();
+
+        let mut config = self.config.clone();
+        let unknown = self.specials.iter().any(|special| special.kind == SpecialTokenKind::Unknown);
+        if unknown && !config.fallback.contains(&Fallback::Unknown) {
+            config.fallback.insert(0, Fallback::Unknown);
+        }
+        if self.byte_fallback && !config.fallback.contains(&Fallback::Bytes) {
+            config.fallback.insert(0, Fallback::Bytes);
+        }
+        let mut specials = self.specials.clone();
+        specials.sort();
+        Ok(Definition {
+            meta: Metadata::default(),
+            model: Model::Unigram { vocab, scores },
+            specials,
+            config,
+        })
+    }
+}
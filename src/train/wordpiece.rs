@@ -0,0 +1,157 @@
+//! Trainer for WordPiece models.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use super::bytepair::merge_pairs;
+use super::{TrainingError, count_words, free_ids};
+use crate::{
+    Configuration, Decoding, Definition, Fallback, InsertionPosition, Metadata, Model,
+    SpecialTokenKind, SpecialVocab, Template, TokenBytes, Vocab,
+};
+
+/// Trainer for WordPiece models.
+///
+/// Learns a [`Model::WordPiece`] vocabulary like the WordPiece trainer of HuggingFace Tokenizers. The vocabulary starts with
+/// all characters of the pre-tokenized training text, and characters following the first character of a part prefixed with `subword_prefix`.
+/// The most frequent pair of adjacent tokens is merged into a new token until the vocabulary reaches the target size
+/// or no pair occurs at least `min_frequency` times.
+///
+/// The trained configuration contains a [`InsertionPosition::WordContinuation`] template with `subword_prefix` and a
+/// [`Decoding::Replace`] removing it from the decoded text. Word continuation templates of the given configuration are replaced.
+/// If a special token of kind [`SpecialTokenKind::Unknown`] is given, [`Fallback::Unknown`] is added to the configuration.
+/// Tokens get the ids not used by the special tokens in ascending order.
+#[derive(Debug, Clone)]
+pub struct WordPieceTrainer {
+    /// The target vocabulary size including the special tokens.
+    pub vocab_size:     usize,
+    /// The special tokens. Removed from the training text before pre-tokenization.
+    pub specials:       SpecialVocab,
+    /// The configuration used for pre-tokenizing the training text and included in the trained definition.
+    pub config:         Configuration,
+    /// The minimum number of occurrences of a pair to be merged.
+    pub min_frequency:  u64,
+    /// The prefix of tokens continuing a word. Must not be empty.
+    pub subword_prefix: String,
+    /// The maximum number of characters in a part encoded into tokens, longer parts are encoded as unknown. `0` disables the limit.
+    pub max_word_chars: u32,
+}
+impl WordPieceTrainer {
+    /// Creates a trainer for the given target vocabulary size, special tokens and configuration.
+    #[inline(never)]
+    pub fn new(vocab_size: usize, specials: SpecialVocab, config: Configuration) -> Self {
+        Self {
+            vocab_size,
+            specials,
+            config,
+            min_frequency: 1,
+            subword_prefix: "##".to_string(),
+            max_word_chars: 100,
+        }
+    }
+
+    /// Trains a definition on the given texts.
+    ///
+    /// Returns the trained definition, or an error if the vocabulary size is smaller than the number of initial characters
+    /// and special tokens, or if the configuration or the special tokens are invalid.
+    #[inline(never)]
+    pub fn train(
+        &self, texts: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Definition, TrainingError> {
+        let words = count_words(texts, &self.specials, &self.config)?;
+        let prefix = self.subword_prefix.as_bytes();
+
+        let mut starts = HashSet::<TokenBytes>::new();
+        let mut continuations = HashSet::<TokenBytes>::new();
+        for (word, _) in &words {
+            for (i, c) in word.char_indices() {
+                let bytes = c.to_string().into_bytes();
+                if i > 0 {
+                    continuations.insert([prefix, &bytes].concat());
+                }
+                starts.insert(bytes);
+            }
+        }
+        let mut starts = starts.into_iter().collect::<Vec<_>>();
+        let mut continuations = continuations.into_iter().collect::<Vec<_>>();
+        starts.sort_unstable();
+        continuations.sort_unstable();
+        let mut tokens = starts;
+        tokens.extend(continuations);
+
+        let required = tokens.len() + self.specials.len();
+        if self.vocab_size < required {
+            return Err(TrainingError::VocabSizeTooSmall(required));
+        }
+
+        let ranks = tokens
+            .iter()
+            .zip(0..)
+            .map(|(bytes, rank)| (bytes.as_slice(), rank))
+            .collect::<HashMap<&[u8], u32>>();
+        let mut words = words
+            .into_iter()
+            .map(|(word, count)| {
+                let symbols = word
+                    .char_indices()
+                    .map(|(i, c)| {
+                        let bytes = c.to_string().into_bytes();
+                        let bytes = if i > 0 {
+                            [prefix, &bytes].concat()
+                        } else {
+                            bytes
+                        };
+                        ranks[bytes.as_slice()]
+                    })
+                    .collect::<Vec<_>>();
+                (symbols, count)
+            })
+            .collect::<Vec<_>>();
+        drop(ranks);
+        words.sort_unstable();
+
+        let size = self.vocab_size - self.specials.len();
+        merge_pairs(&mut words, &mut tokens, size, self.min_frequency, |a, b| {
+            [a, b.strip_prefix(prefix).unwrap_or(b)].concat()
+        });
+
+        let vocab = tokens
+            .into_iter()
+            .zip(free_ids(&self.specials))
+            .map(|(bytes, id)| (bytes, id).into())
+            .collect::<Vocab>();
+
+        let mut config = self.config.clone();
+        config
+            .templates
+            .retain(|template| template.position != InsertionPosition::WordContinuation);
+        config.templates.push(Template {
+            content:  self.subword_prefix.clone(),
+            position: InsertionPosition::WordContinuation,
+        });
+        let decoding = Decoding::Replace {
+            pattern:     self.subword_prefix.clone().into(),
+            replacement: "".to_string(),
+        };
+        if !config.decoding.contains(&decoding) {
+            config.decoding.push(decoding);
+        }
+        let unknown = self.specials.iter().any(|special| special.kind == SpecialTokenKind::Unknown);
+        if unknown && !config.fallback.contains(&Fallback::Unknown) {
+            config.fallback.insert(0, Fallback::Unknown);
+        }
+        let mut specials = self.specials.clone();
+        specials.sort();
+        Ok(Definition {
+            meta: Metadata::default(),
+            model: Model::WordPiece {
+                vocab,
+                max_word_chars: self.max_word_chars,
+            },
+            specials,
+            config,
+        })
+    }
+}
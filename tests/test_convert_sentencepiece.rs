@@ -1,6 +1,6 @@
 //! Test for the conversion of SentencePiece models.
 
-use kitoken::{Definition, Kitoken};
+use kitoken::{Definition, Fallback, Kitoken, Model};
 
 mod util;
 use util::*;
@@ -42,3 +42,158 @@ fn test_mixed_lines() {
         Kitoken::from_sentencepiece_file(model).unwrap()
     })
 }
+
+#[test]
+fn test_mixed_session() {
+    init_env();
+    test_encode_session_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_chunk() {
+    init_env();
+    test_chunk_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_truncate() {
+    init_env();
+    test_truncate_text_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_reader() {
+    init_env();
+    test_encode_reader_full("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_count() {
+    init_env();
+    test_count_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_nbest() {
+    init_env();
+    test_encode_nbest_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_dropout() {
+    init_env();
+    test_encode_dropout_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_score() {
+    init_env();
+    test_score_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_prune() {
+    init_env();
+    test_prune_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_vocab() {
+    init_env();
+    test_vocab_lookup("sentencepiece", "model", |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_stream() {
+    init_env();
+    test_decode_stream_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_options() {
+    init_env();
+    test_decode_options_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_pieces() {
+    init_env();
+    test_decode_pieces_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_offsets() {
+    init_env();
+    test_encode_offsets_lines("sentencepiece", "model", "mixed", false, false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_batch() {
+    init_env();
+    test_encode_batch_lines("sentencepiece", "model", "mixed", false, |model| {
+        Kitoken::from_sentencepiece_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_unigram_byte_fallback() {
+    init_env();
+    let model = test_models_path().join("sentencepiece/xlnet_base_cased.model");
+    let mut definition = Kitoken::from_sentencepiece_file(model).unwrap().to_definition();
+    let Model::Unigram { vocab, scores } = &mut definition.model else {
+        panic!("model is not a unigram model");
+    };
+    let mut next = vocab.iter().map(|token| token.id).max().unwrap() + 1;
+    let mut bytes = Vec::new();
+    for byte in 0..=u8::MAX {
+        let id = match vocab.iter().find(|token| token.bytes == [byte]) {
+            Some(token) => token.id,
+            None => {
+                vocab.push((vec![byte], next).into());
+                scores.push(-100.0);
+                next += 1;
+                next - 1
+            }
+        };
+        bytes.push(id);
+    }
+    definition.config.fallback.insert(0, Fallback::Bytes);
+    let tokenizer = Kitoken::from_definition(definition).unwrap();
+
+    let text = "snow ☃ man";
+    let tokens = tokenizer.encode(text, false).unwrap();
+    let expected = "☃".bytes().map(|byte| bytes[byte as usize]).collect::<Vec<_>>();
+    assert!(
+        tokens.windows(expected.len()).any(|window| window == expected),
+        "byte tokens are in order"
+    );
+    assert_eq!(tokenizer.decode(&tokens, false).unwrap(), text.as_bytes());
+}
@@ -1,6 +1,9 @@
 //! Test for the conversion of OpenAI Tiktoken models.
 
-use kitoken::{Definition, Kitoken};
+use kitoken::{
+    Configuration, Definition, DefinitionError, InitializationError, Kitoken, Metadata, Model,
+    SpecialToken, SpecialTokenKind,
+};
 
 mod util;
 use util::*;
@@ -42,3 +45,244 @@ fn test_mixed_lines() {
         Kitoken::from_tiktoken_file(model).unwrap()
     })
 }
+
+#[test]
+fn test_mixed_session() {
+    init_env();
+    test_encode_session_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_chunk() {
+    init_env();
+    test_chunk_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_truncate() {
+    init_env();
+    test_truncate_text_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_reader() {
+    init_env();
+    test_encode_reader_full("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_count() {
+    init_env();
+    test_count_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_dropout() {
+    init_env();
+    test_encode_dropout_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_score() {
+    init_env();
+    test_score_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_prune() {
+    init_env();
+    test_prune_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_vocab() {
+    init_env();
+    test_vocab_lookup("tiktoken", "tiktoken", |model| Kitoken::from_tiktoken_file(model).unwrap())
+}
+
+#[test]
+fn test_mixed_stream() {
+    init_env();
+    test_decode_stream_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_options() {
+    init_env();
+    test_decode_options_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_pieces() {
+    init_env();
+    test_decode_pieces_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_offsets() {
+    init_env();
+    test_encode_offsets_lines("tiktoken", "tiktoken", "mixed", true, true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_batch() {
+    init_env();
+    test_encode_batch_lines("tiktoken", "tiktoken", "mixed", true, |model| {
+        Kitoken::from_tiktoken_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_encode_templates() {
+    init_env();
+    let tokenizer =
+        Kitoken::from_tiktoken_file(test_models_path().join("tiktoken/llama4.tiktoken")).unwrap();
+    let tokens = tokenizer.encode("Your future belongs to me.", true).unwrap();
+    let templated = tokenizer.encode_with_templates("Your future belongs to me.", true).unwrap();
+    assert_eq!(&templated[1..templated.len() - 1], &tokens[..]);
+    assert_eq!(tokenizer.decode(&templated[..1], true).unwrap(), b"<|begin_of_text|>");
+    assert_eq!(
+        tokenizer.decode(&templated[templated.len() - 1..], true).unwrap(),
+        b"<|end_of_text|>"
+    );
+    let tokenizer =
+        Kitoken::from_tiktoken_file(test_models_path().join("tiktoken/cl100k_base.tiktoken"))
+            .unwrap();
+    let tokens = tokenizer.encode("Your future belongs to me.", true).unwrap();
+    let templated = tokenizer.encode_with_templates("Your future belongs to me.", true).unwrap();
+    assert_eq!(templated, tokens);
+}
+
+#[test]
+fn test_add_remove_special_tokens() {
+    init_env();
+    let mut tokenizer =
+        Kitoken::from_tiktoken_file(test_models_path().join("tiktoken/cl100k_base.tiktoken"))
+            .unwrap();
+    let text = "<|tool_start|>user\nYour future belongs to me.<|tool_end|>";
+    let before = tokenizer.encode(text, true).unwrap();
+    let special = |id: u32, bytes: &[u8]| SpecialToken {
+        id,
+        bytes: bytes.to_vec(),
+        kind: SpecialTokenKind::Control,
+        ident: None,
+        score: 0.0,
+        extract: false,
+    };
+    let size = tokenizer.vocab_size();
+    tokenizer
+        .add_special_tokens([
+            special(200000, b"<|tool_start|>"),
+            special(200001, b"<|tool_end|>"),
+        ])
+        .unwrap();
+    assert_eq!(tokenizer.vocab_size(), size + 2);
+    let tokens = tokenizer.encode(text, true).unwrap();
+    assert_eq!(tokens[0], 200000);
+    assert_eq!(tokens[tokens.len() - 1], 200001);
+    assert_eq!(tokenizer.decode(&tokens, true).unwrap(), text.as_bytes());
+    assert!(tokenizer.is_special(200001));
+    let collisions = [
+        special(0, b"<|new|>"),
+        special(200002, b"<|tool_end|>"),
+        special(200002, b"hello"),
+    ];
+    for collision in collisions {
+        assert!(matches!(
+            tokenizer.add_special_tokens([collision]),
+            Err(InitializationError::SpecialTokenCollision(_))
+        ));
+    }
+    assert_eq!(tokenizer.vocab_size(), size + 2);
+    let removed = tokenizer.remove_special_tokens([200000, 200001, 12345]).unwrap();
+    assert_eq!(removed.len(), 2);
+    assert_eq!(tokenizer.vocab_size(), size);
+    assert_eq!(tokenizer.encode(text, true).unwrap(), before);
+    assert!(!tokenizer.is_special(200001));
+}
+
+#[test]
+fn test_extend_vocab() {
+    init_env();
+    let tokenizer =
+        Kitoken::from_tiktoken_file(test_models_path().join("tiktoken/cl100k_base.tiktoken"))
+            .unwrap();
+    let mut definition = tokenizer.to_definition();
+    let next = definition.next_token_id();
+    assert!(tokenizer.token_to_id(" Kitoken").is_none());
+    assert!(tokenizer.token_to_id(" Kitokenizer").is_none());
+    assert!(matches!(
+        definition.extend_vocab([&b"\xff\xfe\xfd\xfc"[..]]),
+        Err(DefinitionError::UnreachableToken(_))
+    ));
+    assert!(matches!(
+        definition.extend_vocab([" Kitoken", "izer"]),
+        Err(DefinitionError::DuplicateToken(_))
+    ));
+    assert!(matches!(
+        definition.extend_vocab(["<|endoftext|>"]),
+        Err(DefinitionError::DuplicateToken(_))
+    ));
+    assert_eq!(definition.model.vocab().len(), tokenizer.to_definition().model.vocab().len());
+    let ids = definition.extend_vocab([" Kitoken", " Kitokenizer"]).unwrap();
+    assert_eq!(ids, [next, next + 1]);
+    let tokenizer = Kitoken::from_definition(definition).unwrap();
+    assert_eq!(tokenizer.encode(" Kitoken", true).unwrap(), [next]);
+    assert_eq!(tokenizer.encode("Hello Kitokenizer", true).unwrap()[1..], [next + 1]);
+    assert_eq!(tokenizer.id_to_token(next + 1).as_deref(), Some(&b" Kitokenizer"[..]));
+
+    let vocab = ["a", "b", "c", "d", "bc", "ab", "cd"].into_iter().zip(0..);
+    let mut definition = Definition {
+        meta:     Metadata::default(),
+        model:    Model::BytePair {
+            vocab: vocab.map(|(bytes, id)| (bytes.as_bytes().to_vec(), id).into()).collect(),
+            chars: false,
+        },
+        specials: Vec::new(),
+        config:   Configuration::default(),
+    };
+    assert!(matches!(
+        definition.extend_vocab(["abcd"]),
+        Err(DefinitionError::UnreachableToken(_))
+    ));
+    assert_eq!(definition.extend_vocab(["abc", "abcd"]).unwrap(), [7, 8]);
+}
+
+#[test]
+fn test_encode_dropout_long() {
+    init_env();
+    let tokenizer =
+        Kitoken::from_tiktoken_file(test_models_path().join("tiktoken/cl100k_base.tiktoken"))
+            .unwrap();
+    let text = "Kitoken".repeat(100);
+    let tokens = tokenizer.encode(&text, true).unwrap();
+    assert_eq!(tokenizer.encode_dropout(&text, true, 0.0, 7).unwrap(), tokens);
+    assert_eq!(tokenizer.encode_dropout(&text, true, 1.0, 7).unwrap().len(), text.len());
+    let encoded = tokenizer.encode_dropout(&text, true, 0.5, 7).unwrap();
+    assert_ne!(encoded, tokens);
+    assert_eq!(tokenizer.decode(&encoded, true).unwrap(), text.as_bytes());
+}
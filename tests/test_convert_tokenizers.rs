@@ -1,6 +1,8 @@
 //! Test for the conversion of HuggingFace Tokenizers models.
 
-use kitoken::{Definition, Kitoken};
+use kitoken::{
+    Definition, EncodeError, InsertionPosition, Kitoken, Model, SpecialTokenKind, TemplateTypeIds,
+};
 
 mod util;
 use util::*;
@@ -42,3 +44,335 @@ fn test_mixed_lines() {
         Kitoken::from_tokenizers_file(model).unwrap()
     })
 }
+
+#[test]
+fn test_mixed_session() {
+    init_env();
+    test_encode_session_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_chunk() {
+    init_env();
+    test_chunk_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_truncate() {
+    init_env();
+    test_truncate_text_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_reader() {
+    init_env();
+    test_encode_reader_full("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_count() {
+    init_env();
+    test_count_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_nbest() {
+    init_env();
+    test_encode_nbest_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_dropout() {
+    init_env();
+    test_encode_dropout_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_score() {
+    init_env();
+    test_score_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_prune() {
+    init_env();
+    test_prune_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_vocab() {
+    init_env();
+    test_vocab_lookup("tokenizers", "json", |model| Kitoken::from_tokenizers_file(model).unwrap())
+}
+
+#[test]
+fn test_mixed_stream() {
+    init_env();
+    test_decode_stream_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_options() {
+    init_env();
+    test_decode_options_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_decode_pieces() {
+    init_env();
+    test_decode_pieces_lines("tokenizers", "json", "mixed", true, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_mixed_offsets() {
+    init_env();
+    test_encode_offsets_lines("tokenizers", "json", "mixed", true, false, |model| {
+        Kitoken::from_tokenizers_file(model).unwrap()
+    })
+}
+
+#[test]
+fn test_encode_templates() {
+    init_env();
+    let tokenizer =
+        Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/bert_base_cased.json"))
+            .unwrap();
+    let tokens = tokenizer.encode("Your future belongs to me.", true).unwrap();
+    let templated = tokenizer.encode_with_templates("Your future belongs to me.", true).unwrap();
+    assert_eq!(templated.len(), tokens.len() + 2);
+    assert_eq!(&templated[1..templated.len() - 1], &tokens[..]);
+    assert_eq!(tokenizer.decode(&templated[..1], true).unwrap(), b"[CLS]");
+    assert_eq!(tokenizer.decode(&templated[templated.len() - 1..], true).unwrap(), b"[SEP]");
+}
+
+#[test]
+fn test_small_templates() {
+    init_env();
+    let input = read_lines(test_data_path().join("small_input.txt"));
+    // The post-processors of these models insert only the start token
+    for name in ["llama2", "mistral01", "mistral03"] {
+        let reference = read_token_lines(
+            test_data_path().join(["tokenizers/small_tokens_", name, ".txt"].concat()),
+        );
+        let path = test_models_path().join(["tokenizers/", name, ".json"].concat());
+        let tokenizer = Kitoken::from_tokenizers_file(path).unwrap();
+        for (i, (line, tokens)) in input.iter().zip(reference).enumerate() {
+            let templated = tokenizer.encode_with_templates(line, true).unwrap();
+            let expected = [&[1], &tokens[..]].concat();
+            assert_eq!(templated, expected, "{} tokens are equal #{}", name, i + 1);
+        }
+    }
+}
+
+#[test]
+fn test_encode_pair() {
+    init_env();
+    let tokenizer =
+        Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/bert_base_cased.json"))
+            .unwrap();
+    let first = tokenizer.encode("Your future", true).unwrap();
+    let second = tokenizer.encode("belongs to me.", true).unwrap();
+    let pair = tokenizer.encode_pair("Your future", "belongs to me.", true).unwrap();
+    assert_eq!(pair.len(), first.len() + second.len() + 3);
+    assert_eq!(pair.tokens[1..=first.len()], first[..]);
+    assert_eq!(pair.tokens[first.len() + 2..pair.len() - 1], second[..]);
+    assert_eq!(tokenizer.decode(&pair.tokens[..1], true).unwrap(), b"[CLS]");
+    assert_eq!(tokenizer.decode(&pair.tokens[first.len() + 1..][..1], true).unwrap(), b"[SEP]");
+    assert_eq!(tokenizer.decode(&pair.tokens[pair.len() - 1..], true).unwrap(), b"[SEP]");
+    let type_ids = [vec![0; first.len() + 2], vec![1; second.len() + 1]].concat();
+    assert_eq!(pair.type_ids, type_ids);
+    assert_eq!(pair.offsets[1], (0, 4));
+    assert_eq!(pair.offsets[first.len() + 2], (0, 7));
+}
+
+#[test]
+fn test_post_processor_templates() {
+    init_env();
+    let tokenizer = |post_processor: &str| {
+        let data = r#"{
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": [
+                {"id": 0, "content": "[UNK]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true},
+                {"id": 1, "content": "[SEP]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true},
+                {"id": 2, "content": "[CLS]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true}
+            ],
+            "normalizer": null,
+            "pre_tokenizer": {"type": "Whitespace"},
+            "post_processor": POST_PROCESSOR,
+            "decoder": null,
+            "model": {
+                "type": "WordLevel",
+                "vocab": {"[UNK]": 0, "[SEP]": 1, "[CLS]": 2, "your": 3, "future": 4, "belongs": 5, "to": 6, "me": 7},
+                "unk_token": "[UNK]"
+            }
+        }"#;
+        let data = data.replace("POST_PROCESSOR", post_processor);
+        Kitoken::from_tokenizers_slice(data.as_bytes()).unwrap()
+    };
+    let bert = tokenizer(r#"{"type": "BertProcessing", "sep": ["[SEP]", 1], "cls": ["[CLS]", 2]}"#);
+    let templates = &bert.config().templates;
+    let positions = templates.iter().map(|template| template.position).collect::<Vec<_>>();
+    assert_eq!(
+        positions[..2],
+        [InsertionPosition::SequenceEnd, InsertionPosition::SequenceStart],
+        "bert templates are kept"
+    );
+    assert_eq!(bert.encode_with_templates("your future", true).unwrap(), [2, 3, 4, 1]);
+    let pair = bert.encode_pair("your future", "belongs to me", true).unwrap();
+    assert_eq!(pair.tokens, [2, 3, 4, 1, 5, 6, 7, 1]);
+    assert_eq!(pair.type_ids, [0, 0, 0, 0, 1, 1, 1, 1]);
+
+    let roberta = tokenizer(
+        r#"{"type": "RobertaProcessing", "sep": ["[SEP]", 1], "cls": ["[CLS]", 2], "trim_offsets": true, "add_prefix_space": false}"#,
+    );
+    assert_eq!(roberta.encode_with_templates("your future", true).unwrap(), [2, 3, 4, 1]);
+    let pair = roberta.encode_pair("your future", "belongs to me", true).unwrap();
+    assert_eq!(pair.tokens, [2, 3, 4, 1, 1, 5, 6, 7, 1]);
+    assert_eq!(pair.type_ids, [0, 0, 0, 0, 1, 1, 1, 1, 1]);
+
+    let template = tokenizer(
+        r#"{
+            "type": "TemplateProcessing",
+            "single": [{"SpecialToken": {"id": "[CLS]", "type_id": 0}}, {"Sequence": {"id": "A", "type_id": 0}}, {"SpecialToken": {"id": "[SEP]", "type_id": 0}}],
+            "pair": [{"SpecialToken": {"id": "[CLS]", "type_id": 2}}, {"Sequence": {"id": "A", "type_id": 1}}, {"SpecialToken": {"id": "[SEP]", "type_id": 1}}, {"Sequence": {"id": "B", "type_id": 0}}, {"SpecialToken": {"id": "[SEP]", "type_id": 0}}],
+            "special_tokens": {
+                "[CLS]": {"id": "[CLS]", "ids": [2], "tokens": ["[CLS]"]},
+                "[SEP]": {"id": "[SEP]", "ids": [1], "tokens": ["[SEP]"]}
+            }
+        }"#,
+    );
+    assert_eq!(template.encode_with_templates("your future", true).unwrap(), [2, 3, 4, 1]);
+    let pair = template.encode_pair("your future", "belongs to me", true).unwrap();
+    assert_eq!(pair.tokens, [2, 3, 4, 1, 5, 6, 7, 1]);
+    assert_eq!(pair.type_ids, [2, 1, 1, 1, 0, 0, 0, 0]);
+
+    let legacy = std::fs::read(test_models_path().join("llama2.kit")).unwrap();
+    assert_eq!(legacy[7..9], [0, 1], "definition is of version 0.1");
+    let definition = Definition::from_slice(&legacy).unwrap();
+    assert_eq!(definition.config.type_ids, TemplateTypeIds::default());
+    assert_eq!(Definition::from_slice(&definition.to_vec()).unwrap(), definition);
+    Kitoken::from_definition(definition).unwrap();
+}
+
+#[test]
+fn test_encode_batch_padded() {
+    init_env();
+    let texts = ["Your future belongs to me.", "Hello", ""];
+    let tokenizer =
+        Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/bert_base_cased.json"))
+            .unwrap();
+    let batch = tokenizer.encode_batch_padded(&texts, true).unwrap();
+    let longest = tokenizer.encode_with_templates(texts[0], true).unwrap();
+    assert_eq!(batch.shape, (3, longest.len()));
+    assert_eq!(batch.row(0).unwrap(), &longest[..]);
+    let short = tokenizer.encode_with_templates(texts[1], true).unwrap();
+    assert_eq!(&batch.row(1).unwrap()[..short.len()], &short[..]);
+    let mask = [vec![1; short.len()], vec![0; longest.len() - short.len()]].concat();
+    assert_eq!(batch.attention_mask[longest.len()..longest.len() * 2], mask[..]);
+    let special = [vec![1], vec![0; short.len() - 2], vec![
+        1;
+        longest.len()
+            - short.len()
+            + 1
+    ]];
+    assert_eq!(
+        batch.special_tokens_mask[longest.len()..longest.len() * 2],
+        special.concat()[..]
+    );
+    let batch = tokenizer.encode_batch_padded(&["Hello [SEP] world"], true).unwrap();
+    let tokens = tokenizer.encode_with_templates("Hello [SEP] world", true).unwrap();
+    assert_eq!(batch.tokens, tokens);
+    let sep = tokenizer.special_by_ident("sep").unwrap().id;
+    assert_eq!(tokens.iter().filter(|&&token| token == sep).count(), 2);
+    let special = [vec![1], vec![0; tokens.len() - 2], vec![1]];
+    assert_eq!(batch.special_tokens_mask, special.concat());
+    let tokenizer =
+        Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/gte.json")).unwrap();
+    let batch = tokenizer.encode_batch_padded(&texts, true).unwrap();
+    assert_eq!(batch.shape, (3, 512));
+    for (i, text) in texts.iter().enumerate() {
+        let tokens = tokenizer.encode_with_templates(text, true).unwrap();
+        assert_eq!(batch.row(i).unwrap(), &tokens[..]);
+    }
+}
+
+#[test]
+fn test_word_level() {
+    init_env();
+    let data = br#"{
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [
+            {"id": 0, "content": "[UNK]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true},
+            {"id": 1, "content": "[SEP]", "single_word": false, "lstrip": false, "rstrip": false, "normalized": false, "special": true}
+        ],
+        "normalizer": {"type": "Lowercase"},
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": null,
+        "decoder": null,
+        "model": {
+            "type": "WordLevel",
+            "vocab": {"[UNK]": 0, "[SEP]": 1, "your": 2, "future": 3, "belongs": 4, "to": 5, "me": 6, ".": 7},
+            "unk_token": "[UNK]"
+        }
+    }"#;
+    let tokenizer = Kitoken::from_tokenizers_slice(data).unwrap();
+    let definition = tokenizer.to_definition();
+    assert!(matches!(definition.model, Model::WordLevel { .. }));
+    assert_eq!(definition.model.vocab().len(), 6);
+    let tokens = tokenizer.encode("Your future belongs to me.", true).unwrap();
+    assert_eq!(tokens, [2, 3, 4, 5, 6, 7]);
+    let tokens = tokenizer.encode("Your future belongs to Kitoken[SEP]", true).unwrap();
+    assert_eq!(tokens, [2, 3, 4, 5, 0, 1]);
+    assert_eq!(tokenizer.count_tokens("Your tokenizer", true).unwrap(), 2);
+    let encoding = tokenizer.encode_with_offsets("Your future", true).unwrap();
+    assert_eq!(encoding.offsets, [(0, 4), (5, 11)]);
+    assert_eq!(tokenizer.decode([3], true).unwrap(), b"future");
+    assert_eq!(tokenizer.token_to_id("belongs"), Some(4));
+
+    let definition = Definition::from_slice(&definition.to_vec()).unwrap();
+    assert!(matches!(definition.model, Model::WordLevel { .. }));
+    let deserialized = Kitoken::from_definition(definition).unwrap();
+    let text = "Your future belongs to Kitoken.";
+    assert_eq!(deserialized.encode(text, true).unwrap(), tokenizer.encode(text, true).unwrap());
+
+    let mut definition = tokenizer.to_definition();
+    definition.specials.retain(|special| special.kind != SpecialTokenKind::Unknown);
+    definition.config.fallback.clear();
+    let strict = Kitoken::from_definition(definition).unwrap();
+    let mut texts = vec!["your future"; 64];
+    texts.extend(["belongs to kitoken", "me.", "tokenizer"]);
+    for _ in 0..8 {
+        let error = strict.encode_batch(&texts, true).unwrap_err();
+        assert!(matches!(error, EncodeError::InvalidPiece(piece) if piece == b"kitoken"));
+    }
+    let error = strict.encode_batch_padded(&["your future", "me."], true).unwrap_err();
+    assert!(matches!(error, EncodeError::MissingPadToken));
+}
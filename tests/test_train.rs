@@ -0,0 +1,226 @@
+//! Test for the training of Kitoken definitions.
+
+use std::borrow::Cow;
+
+use kitoken::train::{BpeTrainer, TrainingError, UnigramTrainer, WordPieceTrainer};
+use kitoken::{
+    Configuration, Definition, Fallback, InsertionPosition, Kitoken, Metadata, Model, SpecialToken,
+    SpecialTokenKind,
+};
+
+mod util;
+use util::*;
+
+fn specials() -> Vec<SpecialToken> {
+    Vec::from([SpecialToken {
+        id:      0,
+        bytes:   b"<|endoftext|>".to_vec(),
+        kind:    SpecialTokenKind::Control,
+        ident:   Some("eot".to_string()),
+        score:   0.0,
+        extract: true,
+    }])
+}
+
+#[test]
+fn test_train_bpe() {
+    init_env();
+    let config = Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/gpt2.json"))
+        .unwrap()
+        .config()
+        .clone();
+    let lines = read_lines(test_data_path().join("mixed_input.txt"));
+
+    let trainer = BpeTrainer::new(100, specials(), config.clone());
+    assert!(matches!(trainer.train(&lines), Err(TrainingError::VocabSizeTooSmall(257))));
+
+    let trainer = BpeTrainer::new(1000, specials(), config);
+    let definition = trainer.train(&lines).unwrap();
+    assert!(matches!(definition.model, Model::BytePair { chars: false, .. }));
+    assert_eq!(definition.model.vocab().len() + definition.specials.len(), 1000);
+    assert_eq!(definition, trainer.train(&lines).unwrap(), "training is deterministic");
+    assert!(definition.model.vocab().iter().all(|token| token.id != 0));
+
+    let tokenizer = Kitoken::from_definition(definition.clone()).unwrap();
+    let mut tokens = 0;
+    let mut bytes = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let encoded = tokenizer.encode(line, true).unwrap();
+        let decoded = tokenizer.decode(&encoded, true).unwrap();
+        assert_eq!(decoded, line.as_bytes(), "decoded tokens are equal #{}", i + 1);
+        tokens += encoded.len();
+        bytes += line.len();
+    }
+    assert!(tokens * 3 < bytes * 2, "trained tokens merge the training text");
+    let tokens = tokenizer.encode("Hello<|endoftext|>", true).unwrap();
+    assert_eq!(tokens.last(), Some(&0));
+
+    let deserialized = Definition::from_slice(&definition.to_vec()).unwrap();
+    assert_eq!(deserialized, definition);
+
+    let mut trainer = BpeTrainer::new(1000, specials(), tokenizer.config().clone());
+    trainer.min_frequency = u64::MAX;
+    let definition = trainer.train(&lines).unwrap();
+    assert_eq!(definition.model.vocab().len(), 256);
+}
+
+#[test]
+fn test_train_unigram() {
+    init_env();
+    let config = Kitoken::from_sentencepiece_file(
+        test_models_path().join("sentencepiece/xlnet_base_cased.model"),
+    )
+    .unwrap()
+    .config()
+    .clone();
+    let lines = read_lines(test_data_path().join("mixed_input.txt"));
+    let specials = ["<unk>", "<s>", "</s>"]
+        .into_iter()
+        .zip(0..)
+        .map(|(bytes, id)| SpecialToken {
+            id,
+            bytes: bytes.as_bytes().to_vec(),
+            kind: if id == 0 {
+                SpecialTokenKind::Unknown
+            } else {
+                SpecialTokenKind::Control
+            },
+            ident: None,
+            score: 0.0,
+            extract: false,
+        })
+        .collect::<Vec<_>>();
+
+    let mut trainer = UnigramTrainer::new(100, specials.clone(), config.clone());
+    trainer.character_coverage = 0.98;
+    assert!(matches!(trainer.train(&lines), Err(TrainingError::VocabSizeTooSmall(_))));
+
+    let mut trainer = UnigramTrainer::new(4000, specials, config.clone());
+    trainer.character_coverage = 0.98;
+    trainer.byte_fallback = true;
+    let definition = trainer.train(&lines).unwrap();
+    let Model::Unigram { vocab, scores } = &definition.model else {
+        panic!("trained model is not unigram");
+    };
+    assert_eq!(vocab.len(), scores.len());
+    assert!(vocab.len() + definition.specials.len() <= 4000);
+    assert!(vocab.iter().all(|token| token.id > 2));
+    assert!(scores.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!((0..=u8::MAX).all(|byte| vocab.iter().any(|token| token.bytes == [byte])));
+    assert_eq!(definition.config.fallback.first(), Some(&Fallback::Bytes));
+    assert!(definition.config.fallback.contains(&Fallback::Unknown));
+    assert_eq!(definition, trainer.train(&lines).unwrap(), "training is deterministic");
+
+    let tokenizer = Kitoken::from_definition(definition.clone()).unwrap();
+    let reference = Kitoken::new(Model::Chars, Vec::new(), config, Metadata::default()).unwrap();
+    let mut tokens = 0;
+    let mut chars = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let encoded = tokenizer.encode(line, true).unwrap();
+        let decoded = tokenizer.decode(&encoded, true).unwrap();
+        let expected = reference.decode(reference.encode(line, true).unwrap(), true).unwrap();
+        assert_eq!(decoded, expected, "decoded tokens are equal #{}", i + 1);
+        assert!(!encoded.contains(&0), "no unknown tokens #{}", i + 1);
+        tokens += encoded.len();
+        chars += line.chars().count();
+    }
+    assert!(tokens < chars, "trained tokens merge the training text");
+    assert_eq!(tokenizer.encode_nbest(&lines[1], true, 2).unwrap().len(), 2);
+
+    let deserialized = Definition::from_slice(&definition.to_vec()).unwrap();
+    assert_eq!(deserialized, definition);
+}
+
+#[test]
+fn test_train_unigram_seed_size() {
+    init_env();
+    let text = "abcdefg abcdefg abcdefg hijk hijk";
+    let mut trainer = UnigramTrainer::new(16, Vec::new(), Configuration::default());
+    trainer.seed_size = 5;
+    let definition = trainer.train([text]).unwrap();
+    let vocab = definition.model.vocab();
+    for c in text.chars() {
+        let bytes = c.to_string().into_bytes();
+        assert!(vocab.iter().any(|token| token.bytes == bytes), "single characters are kept");
+    }
+
+    let tokenizer = Kitoken::from_definition(definition).unwrap();
+    let encoded = tokenizer.encode(text, true).unwrap();
+    assert_eq!(tokenizer.decode(&encoded, true).unwrap(), text.as_bytes());
+
+    assert!(matches!(trainer.train([""]), Err(TrainingError::EmptyVocab)));
+}
+
+#[test]
+fn test_train_wordpiece() {
+    init_env();
+    let config =
+        Kitoken::from_tokenizers_file(test_models_path().join("tokenizers/bert_base_cased.json"))
+            .unwrap()
+            .config()
+            .clone();
+    let lines = read_lines(test_data_path().join("mixed_input.txt"));
+    let specials = ["[UNK]", "[CLS]", "[SEP]"]
+        .into_iter()
+        .zip(0..)
+        .map(|(bytes, id)| SpecialToken {
+            id,
+            bytes: bytes.as_bytes().to_vec(),
+            kind: if id == 0 {
+                SpecialTokenKind::Unknown
+            } else {
+                SpecialTokenKind::Control
+            },
+            ident: None,
+            score: 0.0,
+            extract: true,
+        })
+        .collect::<Vec<_>>();
+
+    let trainer = WordPieceTrainer::new(100, specials.clone(), config.clone());
+    assert!(matches!(trainer.train(&lines), Err(TrainingError::VocabSizeTooSmall(_))));
+
+    let mut trainer = WordPieceTrainer::new(8000, specials, config.clone());
+    trainer.max_word_chars = 0;
+    let definition = trainer.train(&lines).unwrap();
+    let Model::WordPiece {
+        vocab,
+        max_word_chars,
+    } = &definition.model
+    else {
+        panic!("trained model is not wordpiece");
+    };
+    assert_eq!(*max_word_chars, 0);
+    assert_eq!(vocab.len() + definition.specials.len(), 8000);
+    assert!(vocab.iter().all(|token| token.id > 2));
+    assert!(vocab.iter().any(|token| token.starts_with(b"##")));
+    assert!(definition.config.templates.iter().any(|template| {
+        template.position == InsertionPosition::WordContinuation && template.content == "##"
+    }));
+    assert_eq!(definition.config.fallback.first(), Some(&Fallback::Unknown));
+    assert_eq!(definition.config.decoding, config.decoding);
+    assert_eq!(definition, trainer.train(&lines).unwrap(), "training is deterministic");
+
+    let tokenizer = Kitoken::from_definition(definition.clone()).unwrap();
+    let mut tokens = 0;
+    let mut chars = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let encoded = tokenizer.encode(line, true).unwrap();
+        let decoded = tokenizer.decode(&encoded, true).unwrap();
+        let mut normalized = Cow::Borrowed(line.as_str());
+        config.normalize(&mut normalized, 0..line.len());
+        let expected = normalized.split_whitespace().collect::<String>();
+        let decoded = String::from_utf8(decoded).unwrap();
+        let decoded = decoded.split_whitespace().collect::<String>();
+        assert_eq!(decoded, expected, "decoded tokens are equal #{}", i + 1);
+        assert!(!encoded.contains(&0), "no unknown tokens #{}", i + 1);
+        tokens += encoded.len();
+        chars += line.chars().count();
+    }
+    assert!(tokens < chars, "trained tokens merge the training text");
+    let encoded = tokenizer.encode("[CLS]", true).unwrap();
+    assert_eq!(encoded, [1]);
+
+    let deserialized = Definition::from_slice(&definition.to_vec()).unwrap();
+    assert_eq!(deserialized, definition);
+}
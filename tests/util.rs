@@ -6,7 +6,10 @@ use std::sync::Once;
 use bstr::ByteSlice;
 use console::style;
 
-use kitoken::{Definition, Kitoken, Model};
+use kitoken::{
+    DecodeError, DecodeOptions, Definition, EncodeError, EncodeReaderError, Kitoken, Model,
+    Normalization, ProcessingDirection, TokenId, UnknownTokenBehavior,
+};
 
 static INIT_ENV: Once = Once::new();
 
@@ -153,6 +156,598 @@ pub fn test_encode_decode_full(
     }
 }
 
+pub fn test_model_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>,
+    init: impl Fn(&Path) -> Kitoken, test: impl Fn(Kitoken, &[String]),
+) {
+    eprintln!();
+    let input = input.as_ref();
+    let input_lines = read_lines(test_data_path().join([input, "_input.txt"].concat()));
+    for model in test_models(path, extension) {
+        log::info!("{}_input.txt: {}", input, model.to_string_lossy());
+        test(init(&model), &input_lines);
+    }
+}
+
+pub fn test_encode_offsets_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    exact: bool, init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let encoding = tokenizer.encode_with_offsets(line, specials).unwrap();
+            assert_eq!(encoding.tokens, tokens, "tokens are equal #{}", i + 1);
+            assert_eq!(
+                encoding.offsets.len(),
+                tokens.len(),
+                "offsets lengths are equal #{}",
+                i + 1
+            );
+            let mut last = 0;
+            for (&token, &(start, end)) in encoding.tokens.iter().zip(encoding.offsets.iter()) {
+                assert!(start <= end && end <= line.len(), "offsets are in bounds #{}", i + 1);
+                if end > start {
+                    assert!(start >= last, "offsets are ordered #{}", i + 1);
+                    last = start;
+                }
+                if exact {
+                    let bytes = tokenizer.decode([token], specials).unwrap();
+                    assert_eq!(
+                        bytes.as_bstr(),
+                        line.as_bytes()[start..end].as_bstr(),
+                        "offsets match token bytes #{}",
+                        i + 1
+                    );
+                }
+            }
+        }
+    })
+}
+
+pub fn test_encode_batch_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let batch = tokenizer.encode_batch(input_lines, specials).unwrap();
+        assert_eq!(batch.len(), input_lines.len(), "batch lengths are equal");
+        for (i, (line, tokens)) in input_lines.iter().zip(batch.iter()).enumerate() {
+            assert_eq!(
+                tokens,
+                &tokenizer.encode(line, specials).unwrap(),
+                "tokens are equal #{}",
+                i + 1
+            );
+        }
+    })
+}
+
+pub fn test_decode_stream_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let decoded = tokenizer.decode(&tokens, specials).unwrap();
+            let mut stream = tokenizer.decode_stream(specials);
+            let mut streamed = String::new();
+            for &token in &tokens {
+                if let Some(text) = stream.step(token).unwrap() {
+                    streamed.push_str(&text);
+                }
+            }
+            assert_eq!(
+                streamed,
+                String::from_utf8_lossy(&decoded),
+                "streamed text is equal #{}",
+                i + 1
+            );
+        }
+    })
+}
+
+pub fn test_decode_options_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let unknown = (tokenizer.vocab_size() as TokenId..)
+            .find(|&id| tokenizer.id_to_token(id).is_none())
+            .unwrap();
+        let skip = DecodeOptions::with_unknown(UnknownTokenBehavior::Skip);
+        let replace = DecodeOptions::with_unknown(UnknownTokenBehavior::Replace("<?>".into()));
+        let replacement = DecodeOptions::with_unknown(UnknownTokenBehavior::ReplacementCharacter);
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let decoded = tokenizer.decode(&tokens, specials).unwrap();
+            let text = tokenizer.decode_str(&tokens, specials, &DecodeOptions::default()).unwrap();
+            assert_eq!(text, String::from_utf8_lossy(&decoded), "decoded text is equal #{}", i + 1);
+            for end in 0..tokens.len().min(8) {
+                let text = tokenizer
+                    .decode_str(&tokens[..end], specials, &DecodeOptions::default())
+                    .unwrap();
+                let decoded = tokenizer.decode(&tokens[..end], specials).unwrap();
+                assert_eq!(
+                    text,
+                    String::from_utf8_lossy(&decoded),
+                    "partial text is equal #{}",
+                    i + 1
+                );
+            }
+
+            let mut invalid = tokens.clone();
+            invalid.insert(tokens.len() / 2, unknown);
+            assert!(
+                matches!(
+                    tokenizer.decode(&invalid, specials),
+                    Err(DecodeError::InvalidToken(id)) if id == unknown
+                ),
+                "unknown token is rejected #{}",
+                i + 1
+            );
+            let skipped = tokenizer.decode_with_options(&invalid, specials, &skip).unwrap();
+            assert_eq!(skipped, decoded, "unknown token is skipped #{}", i + 1);
+            let replaced = tokenizer.decode_str(&invalid, specials, &replace).unwrap();
+            assert!(replaced.contains("<?>"), "unknown token is replaced #{}", i + 1);
+            let replaced = tokenizer.decode_str(&invalid, specials, &replacement).unwrap();
+            assert!(replaced.contains('\u{FFFD}'), "unknown token is replaced #{}", i + 1);
+        }
+    })
+}
+
+pub fn test_decode_pieces_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let decoded = tokenizer.decode(&tokens, specials).unwrap();
+            let pieces = tokenizer.decode_pieces(&tokens, specials).unwrap();
+            assert_eq!(pieces.len(), tokens.len(), "piece count is equal #{}", i + 1);
+            let mut position = 0;
+            for (j, (piece, &token)) in pieces.iter().zip(&tokens).enumerate() {
+                assert_eq!(piece.id, token, "piece id is equal #{}", i + 1);
+                let bytes = tokenizer.id_to_token(token).unwrap();
+                assert_eq!(piece.bytes, bytes.as_ref(), "piece bytes are equal #{}", i + 1);
+                let (start, end) = piece.range;
+                assert!(position <= start && start <= end, "piece ranges are sorted #{}", i + 1);
+                assert!(
+                    decoded[position..start].iter().all(u8::is_ascii_whitespace),
+                    "only whitespace is inserted between pieces #{}",
+                    i + 1
+                );
+                if j > 0 && j + 1 < pieces.len() && !piece.text.contains('\u{FFFD}') {
+                    assert_eq!(
+                        decoded[start..end].to_str_lossy(),
+                        piece.text,
+                        "piece text is equal #{}",
+                        i + 1
+                    );
+                }
+                position = end;
+            }
+            assert!(position <= decoded.len(), "piece ranges are in bounds #{}", i + 1);
+            let concatenated = pieces
+                .iter()
+                .filter(|piece| !piece.special || piece.range.0 < piece.range.1)
+                .map(|piece| piece.text.as_str())
+                .collect::<String>();
+            if !concatenated.contains('\u{FFFD}') {
+                let text = |text: &str| text.split_whitespace().collect::<String>();
+                assert_eq!(
+                    text(&concatenated),
+                    text(&decoded.to_str_lossy()),
+                    "concatenated pieces are equal to the decoded text #{}",
+                    i + 1
+                );
+            }
+            assert!(
+                pieces.first().is_none_or(|piece| piece.word_start),
+                "first piece starts a word #{}",
+                i + 1
+            );
+        }
+    })
+}
+
+pub fn test_count_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let count = tokenizer.count_tokens(line, specials).unwrap();
+            assert_eq!(count, tokens.len(), "token counts are equal #{}", i + 1);
+        }
+    })
+}
+
+pub fn test_vocab_lookup(
+    path: impl AsRef<str>, extension: impl AsRef<str>, init: impl Fn(&Path) -> Kitoken,
+) {
+    eprintln!();
+    let path = path.as_ref();
+    let extension = extension.as_ref();
+    for model in test_models(path, extension) {
+        log::info!("vocab: {}", model.to_string_lossy());
+        let tokenizer = init(&model);
+        let definition = tokenizer.to_definition();
+        let mut ids = std::collections::HashSet::new();
+        for token in definition.model.vocab() {
+            ids.insert(token.id);
+            let id = tokenizer.token_to_id(&token.bytes).expect("token bytes are found");
+            assert_eq!(
+                tokenizer.id_to_token(id).as_deref(),
+                Some(&token.bytes[..]),
+                "token bytes are equal"
+            );
+            assert!(tokenizer.id_to_token(token.id).is_some(), "token id is found");
+        }
+        for special in &definition.specials {
+            ids.insert(special.id);
+            assert!(tokenizer.is_special(special.id), "special token is special");
+            assert!(tokenizer.token_to_id(&special.bytes).is_some(), "special bytes are found");
+            if let Some(ident) = &special.ident {
+                let found = tokenizer.special_by_ident(ident).expect("special ident is found");
+                assert_eq!(found.ident.as_ref(), Some(ident), "special idents are equal");
+            }
+        }
+        assert_eq!(tokenizer.vocab_size(), ids.len(), "vocab sizes are equal");
+        assert!(tokenizer.special_by_ident("not-an-ident").is_none());
+    }
+}
+
+pub fn test_prune_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let tokens = input_lines
+            .iter()
+            .map(|line| tokenizer.encode(line, specials).unwrap())
+            .collect::<Vec<_>>();
+        let used = tokens.iter().flatten().copied().collect::<std::collections::HashSet<_>>();
+        let mut definition = tokenizer.to_definition();
+        let vocab_len = definition.model.vocab().len();
+        let ids = definition.prune(|token| {
+            let unit = token.bytes.len() == 1 || token.bytes.chars().count() == 1;
+            used.contains(&token.id) || unit
+        });
+        assert!(definition.model.vocab().len() < vocab_len, "vocab is pruned");
+        let pruned = Kitoken::from_definition(definition).unwrap();
+        assert_eq!(pruned.vocab_size(), ids.len(), "vocab size matches id map");
+        for (i, (line, tokens)) in input_lines.iter().zip(tokens).enumerate() {
+            let remapped = tokens.iter().map(|id| ids[id]).collect::<Vec<_>>();
+            let encoded = pruned.encode(line, specials).unwrap();
+            assert_eq!(encoded, remapped, "pruned tokens are equal #{}", i + 1);
+        }
+    })
+}
+
+pub fn test_encode_dropout_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        if !matches!(tokenizer.to_definition().model, Model::BytePair { .. }) {
+            let encoded = tokenizer.encode_dropout("Hello", specials, 0.3, 0);
+            assert!(matches!(encoded, Err(EncodeError::UnsupportedModel)));
+            return;
+        }
+        let mut count = 0;
+        let mut dropout_count = 0;
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let decoded = tokenizer.decode(&tokens, true).unwrap();
+            let encoded = tokenizer.encode_dropout(line, specials, 0.0, i as u64).unwrap();
+            assert_eq!(encoded, tokens, "tokens without dropout are equal #{}", i + 1);
+            let encoded = tokenizer.encode_dropout(line, specials, 0.3, i as u64).unwrap();
+            let repeated = tokenizer.encode_dropout(line, specials, 0.3, i as u64).unwrap();
+            assert_eq!(encoded, repeated, "tokens with the same seed are equal #{}", i + 1);
+            assert_eq!(
+                tokenizer.decode(&encoded, true).unwrap(),
+                decoded,
+                "decoded dropout tokens are equal #{}",
+                i + 1
+            );
+            count += tokens.len();
+            dropout_count += encoded.len();
+        }
+        assert!(dropout_count > count, "dropout skips merges");
+    })
+}
+
+pub fn test_encode_nbest_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let mut definition = tokenizer.to_definition();
+        definition.config.processing.clear();
+        let Model::Unigram { vocab, scores } = &definition.model else {
+            let tokenizer = Kitoken::from_definition(definition).unwrap();
+            let nbest = tokenizer.encode_nbest("Hello", specials, 2);
+            assert!(matches!(nbest, Err(EncodeError::UnsupportedModel)));
+            return;
+        };
+        let scores = vocab
+            .iter()
+            .zip(scores)
+            .map(|(token, score)| (token.id, *score as f64))
+            .collect::<std::collections::HashMap<_, _>>();
+        let tokenizer = Kitoken::from_definition(definition.clone()).unwrap();
+        let mut sampled = 0;
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let scored = tokens.iter().all(|token| scores.contains_key(token));
+            let decoded = tokenizer.decode(&tokens, true).unwrap();
+            let nbest = tokenizer.encode_nbest(line, specials, 4).unwrap();
+            assert!(!nbest.is_empty() && nbest.len() <= 4, "n-best length #{}", i + 1);
+            if scored {
+                let log_prob = tokens.iter().map(|token| scores[token]).sum::<f64>();
+                assert!((nbest[0].log_prob - log_prob).abs() < 1e-3, "best is best #{}", i + 1);
+            } else {
+                assert_eq!(nbest[0].tokens, tokens, "best is best #{}", i + 1);
+            }
+            for (j, segmentation) in nbest.iter().enumerate() {
+                let decoded_segmentation = tokenizer.decode(&segmentation.tokens, true).unwrap();
+                assert_eq!(decoded_segmentation, decoded, "n-best decodes #{}", i + 1);
+                if segmentation.tokens.iter().all(|token| scores.contains_key(token)) {
+                    let log_prob =
+                        segmentation.tokens.iter().map(|token| scores[token]).sum::<f64>();
+                    assert!((segmentation.log_prob - log_prob).abs() < 1e-3);
+                }
+                if j > 0 {
+                    assert!(nbest[j - 1].log_prob >= segmentation.log_prob, "n-best is ordered");
+                    assert_ne!(nbest[j - 1].tokens, segmentation.tokens, "n-best is unique");
+                }
+            }
+            let sample = tokenizer.encode_sample(line, specials, 0.1, 0, i as u64).unwrap();
+            let repeated = tokenizer.encode_sample(line, specials, 0.1, 0, i as u64).unwrap();
+            assert_eq!(sample, repeated, "samples with the same seed are equal #{}", i + 1);
+            assert_eq!(tokenizer.decode(&sample.tokens, true).unwrap(), decoded);
+            assert!(sample.log_prob <= nbest[0].log_prob + 1e-3, "sample is not better");
+            sampled += (sample.tokens != tokens) as usize;
+            let sample = tokenizer.encode_sample(line, specials, 0.1, 4, i as u64).unwrap();
+            assert!(nbest.contains(&sample), "n-best sample is in n-best #{}", i + 1);
+        }
+        let text = "Your future belongs to me, kitoken tokenizer.";
+        let tokens = tokenizer.encode(text, specials).unwrap();
+        for seed in 0..16 {
+            let sample = tokenizer.encode_sample(text, specials, 0.1, 0, seed).unwrap();
+            sampled += (sample.tokens != tokens) as usize;
+        }
+        assert!(sampled > 0, "sampling produces different segmentations");
+    })
+}
+
+pub fn test_score_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let mut definition = tokenizer.to_definition();
+        definition.config.processing.clear();
+        let unigram = matches!(definition.model, Model::Unigram { .. });
+        let merges = matches!(definition.model, Model::BytePair { .. });
+        let tokenizer = Kitoken::from_definition(definition).unwrap();
+        let mut non_canonical = 0;
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            let canonical = tokenizer.is_canonical(line, specials, &tokens).unwrap();
+            assert!(canonical, "encoded tokens are canonical #{}", i + 1);
+            if !unigram {
+                let score = tokenizer.score(&tokens);
+                assert!(matches!(score, Err(EncodeError::UnsupportedModel)));
+                if !merges {
+                    continue;
+                }
+                let encoded = tokenizer.encode_dropout(line, specials, 0.5, i as u64).unwrap();
+                let canonical = tokenizer.is_canonical(line, specials, &encoded).unwrap();
+                assert_eq!(canonical, encoded == tokens, "dropout tokens are canonical #{}", i + 1);
+                non_canonical += !canonical as usize;
+                continue;
+            }
+            let nbest = tokenizer.encode_nbest(line, specials, 4).unwrap();
+            for (j, segmentation) in nbest.iter().enumerate() {
+                let score = tokenizer.score(&segmentation.tokens).unwrap();
+                assert!((score - segmentation.log_prob).abs() < 1e-3, "score #{}", i + 1);
+                let canonical = tokenizer.is_canonical(line, specials, &segmentation.tokens);
+                assert_eq!(canonical.unwrap(), j == 0, "n-best tokens are canonical #{}", i + 1);
+                non_canonical += (j > 0) as usize;
+            }
+        }
+        if unigram {
+            let size = tokenizer.vocab_size() as TokenId;
+            let invalid = (size..).find(|&id| tokenizer.id_to_token(id).is_none()).unwrap();
+            let score = tokenizer.score([invalid]);
+            assert!(matches!(score, Err(EncodeError::InvalidToken(id)) if id == invalid));
+        }
+        if unigram || merges {
+            assert!(non_canonical > 0, "non-canonical tokens are detected");
+        }
+    })
+}
+
+pub fn test_truncate_text_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let mut definition = tokenizer.to_definition();
+        definition.config.processing.clear();
+        let tokenizer = Kitoken::from_definition(definition).unwrap();
+        for (i, line) in input_lines.iter().enumerate() {
+            let count = tokenizer.encode(line, specials).unwrap().len();
+            for max_tokens in [0, 1, count / 2, count] {
+                let cut = tokenizer
+                    .truncate_text(line, max_tokens, ProcessingDirection::Right, specials)
+                    .unwrap();
+                let kept = tokenizer.encode(&line[..cut], specials).unwrap();
+                assert!(kept.len() <= max_tokens, "prefix fits #{} ({})", i + 1, max_tokens);
+                let cut = tokenizer
+                    .truncate_text(line, max_tokens, ProcessingDirection::Left, specials)
+                    .unwrap();
+                let kept = tokenizer.encode(&line[cut..], specials).unwrap();
+                assert!(kept.len() <= max_tokens, "suffix fits #{} ({})", i + 1, max_tokens);
+            }
+            let cut =
+                tokenizer.truncate_text(line, usize::MAX, ProcessingDirection::Right, specials);
+            assert_eq!(cut.unwrap(), line.len(), "full text fits #{}", i + 1);
+        }
+    })
+}
+
+pub fn test_chunk_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let mut definition = tokenizer.to_definition();
+        definition.config.processing.clear();
+        let tokenizer = Kitoken::from_definition(definition).unwrap();
+        for (i, line) in input_lines.iter().enumerate() {
+            let encoding = tokenizer.encode_with_offsets(line, specials).unwrap();
+            let tokens = encoding.tokens;
+            for (max_tokens, overlap) in [(1, 0), (4, 0), (4, 2), (16, 4)] {
+                let mut position = 0;
+                let mut last = 0;
+                for (range, chunk) in tokenizer.chunk(line, max_tokens, overlap, specials).unwrap()
+                {
+                    assert!(
+                        !chunk.is_empty() && chunk.len() <= max_tokens,
+                        "chunk fits #{}",
+                        i + 1
+                    );
+                    assert!(
+                        range.start <= range.end && range.end <= line.len(),
+                        "range is in bounds #{}",
+                        i + 1
+                    );
+                    assert!(range.start >= last, "ranges are ordered #{}", i + 1);
+                    last = range.start;
+                    let start = (position.max(overlap) - overlap..=position)
+                        .find(|&start| {
+                            let offsets = &encoding.offsets[start..];
+                            tokens[start..].starts_with(&chunk)
+                                && offsets[..chunk.len()].iter().map(|o| o.0).min()
+                                    == Some(range.start)
+                                && offsets[..chunk.len()].iter().map(|o| o.1).max()
+                                    == Some(range.end)
+                        })
+                        .unwrap_or_else(|| panic!("chunk continues the tokens #{}", i + 1));
+                    position = start + chunk.len();
+                }
+                assert_eq!(position, tokens.len(), "chunks cover the tokens #{}", i + 1);
+            }
+        }
+    })
+}
+
+pub fn test_encode_session_lines(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    test_model_lines(path, extension, input, init, |tokenizer, input_lines| {
+        let mut session = tokenizer.encoder_state();
+        let mut all = Vec::new();
+        let mut expected = Vec::new();
+        for (i, line) in input_lines.iter().enumerate() {
+            let tokens = tokenizer.encode(line, specials).unwrap();
+            assert_eq!(
+                session.encode(line, specials).unwrap(),
+                tokens,
+                "tokens are equal #{}",
+                i + 1
+            );
+            session.encode_into(line, specials, &mut all).unwrap();
+            expected.extend(tokens);
+        }
+        assert_eq!(all, expected, "appended tokens are equal");
+    })
+}
+
+pub fn test_encode_reader_full(
+    path: impl AsRef<str>, extension: impl AsRef<str>, input: impl AsRef<str>, specials: bool,
+    init: impl Fn(&Path) -> Kitoken,
+) {
+    eprintln!();
+    let path = path.as_ref();
+    let extension = extension.as_ref();
+    let input = input.as_ref();
+    let input_full = read_full(test_data_path().join([input, "_input.txt"].concat()));
+    for model in test_models(path, extension) {
+        log::info!("{}_input.txt: {}", input, model.to_string_lossy());
+        let mut definition = init(&model).to_definition();
+        definition.config.processing.clear();
+        let tokenizer = Kitoken::from_definition(definition).unwrap();
+        let expected = tokenizer.encode(&input_full, specials).unwrap();
+        // inputs are only cut between split parts
+        let config = tokenizer.config();
+        let split = !config.split.is_empty()
+            && !config.normalization.iter().any(|normalization| {
+                matches!(
+                    normalization,
+                    Normalization::Append { .. }
+                        | Normalization::Prepend { .. }
+                        | Normalization::Extend { .. }
+                        | Normalization::Strip { .. }
+                )
+            });
+        for buffer_size in [61, 1024, 1 << 16] {
+            let reader = std::io::Cursor::new(input_full.as_bytes());
+            let chunks = tokenizer
+                .encode_reader(reader, specials)
+                .with_buffer_size(buffer_size)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert!(chunks.iter().all(|chunk| !chunk.is_empty()), "chunks are not empty");
+            if split && buffer_size <= 1024 {
+                assert!(chunks.len() > 1, "input is cut with buffer size {}", buffer_size);
+            }
+            let tokens = chunks.concat();
+            assert_eq!(tokens, expected, "tokens are equal with buffer size {}", buffer_size);
+        }
+        // reaching the buffer limit without a cut is an error
+        for buffer_size in [61, 1024] {
+            let reader = std::io::Cursor::new(input_full.as_bytes());
+            let result = tokenizer
+                .encode_reader(reader, specials)
+                .with_buffer_size(buffer_size)
+                .with_max_buffered(buffer_size * 4)
+                .collect::<Result<Vec<_>, _>>();
+            match result {
+                Ok(chunks) => assert_eq!(chunks.concat(), expected, "tokens are equal"),
+                Err(EncodeReaderError::BufferLimit(limit)) => assert_eq!(limit, buffer_size * 4),
+                Err(error) => panic!("unexpected error: {}", error),
+            }
+        }
+        let mut definition = tokenizer.to_definition();
+        definition.config.split.clear();
+        let unsplit = Kitoken::from_definition(definition).unwrap();
+        let text = "hello world ".repeat(100);
+        let reader = std::io::Cursor::new(text.as_bytes());
+        let result = unsplit
+            .encode_reader(reader, specials)
+            .with_buffer_size(64)
+            .with_max_buffered(256)
+            .collect::<Result<Vec<_>, _>>();
+        assert!(
+            matches!(result, Err(EncodeReaderError::BufferLimit(256))),
+            "unsplit input is not cut"
+        );
+        let mut invalid = input_full.as_bytes().to_vec();
+        invalid.insert(invalid.len() / 2, 0xff);
+        let reader = std::io::Cursor::new(invalid);
+        let result = tokenizer.encode_reader(reader, specials).collect::<Result<Vec<_>, _>>();
+        assert!(result.is_err(), "invalid utf-8 is rejected");
+    }
+}
+
 pub fn read_lines(path: impl Into<PathBuf>) -> Vec<String> {
     let path = path.into();
     let lines = std::fs::read_to_string(path).unwrap();